@@ -12,7 +12,7 @@ enum Paint {
 
 fn paint_hull(program: &Vec<Intcode>, start_tile_col: Paint) -> HashMap<Vec2D, Paint> {
     let (input, sink) = channel();
-    let output = exec(program, sink, None);
+    let (output, robot) = spawn(Vm::new(program), sink, None);
     let mut hull = HashMap::new();
     let mut pos = Vec2D::default();
     let mut dir = Vec2D::from_dir(Dir::Up);
@@ -42,6 +42,7 @@ fn paint_hull(program: &Vec<Intcode>, start_tile_col: Paint) -> HashMap<Vec2D, P
             Paint::Black
         };
     }
+    assert_eq!(robot.join(), Exit::Halted);
     hull
 }
 
@@ -89,9 +90,6 @@ pub struct Day11 {
 
 pub fn solution(lines: Vec<&str>) -> Box<dyn Solution> {
     Box::new(Day11 {
-        program: lines[0]
-            .split(",")
-            .map(|ic| ic.parse::<Intcode>().unwrap())
-            .collect(),
+        program: Program::parse(&lines.join("\n")).unwrap().into(),
     })
 }
@@ -1,6 +1,5 @@
 use std::collections::HashMap;
 use std::env;
-use std::sync::mpsc::*;
 use super::intcode::*;
 use super::Solution;
 use super::vec2d::*;
@@ -31,10 +30,10 @@ fn to_tile(tile_id: Intcode, x: Intcode, y: Intcode) -> Tile {
     }
 }
 
-fn next_tile(output: &Receiver<Intcode>) -> Option<(Vec2D, Tile)> {
-    if let Ok(x) = output.recv() {
-        let y = output.recv().unwrap();
-        let tile_id = output.recv().unwrap();
+fn next_tile(vm: &mut Vm) -> Option<(Vec2D, Tile)> {
+    if let Some(x) = vm.output().unwrap() {
+        let y = vm.output().unwrap().unwrap();
+        let tile_id = vm.output().unwrap().unwrap();
         Some((
             Vec2D::from(x as Coord, y as Coord),
             to_tile(tile_id, x, y),
@@ -80,10 +79,9 @@ fn render(screen: &Screen) {
 
 impl Solution for Day13 {
     fn part1(&self) -> String {
-        let (_, sink) = channel();
-        let output = exec(&self.program, sink, None);
+        let mut vm = Vm::new(&self.program);
         let mut blocks = 0;
-        while let Some(tile) = next_tile(&output) {
+        while let Some(tile) = next_tile(&mut vm) {
             if tile.1 == Tile::Block {
                 blocks = blocks + 1;
             }
@@ -96,12 +94,11 @@ impl Solution for Day13 {
 
         let mut free_play_program = self.program.clone();
         free_play_program[0] = 2;
-        let (joystick, sink) = channel();
-        let output = exec(&free_play_program, sink, None);
+        let mut vm = Vm::new(&free_play_program);
         let mut paddle = Vec2D::default();
         let mut final_score = 0;
         let mut screen = Screen::new();
-        while let Some(tile) = next_tile(&output) {
+        while let Some(tile) = next_tile(&mut vm) {
             screen.insert(tile.0, tile.1);
             match tile.1 {
                 Tile::Score(val) => final_score = val,
@@ -113,15 +110,14 @@ impl Solution for Day13 {
                 }
                 Tile::Ball => {
                     let ball = tile.0;
-                    joystick.send(
+                    vm.push_input(
                         if ball.x() < paddle.x() {
                             -1
                         } else if ball.x() > paddle.x() {
                             1
                         } else {
                             0
-                        })
-                        .unwrap();
+                        });
                 }
                 _ => (),
             }
@@ -137,9 +133,6 @@ pub struct Day13 {
 
 pub fn solution(lines: Vec<&str>) -> Box<dyn Solution> {
     Box::new(Day13 {
-        program: lines[0]
-            .split(",")
-            .map(|ic| ic.parse::<Intcode>().unwrap())
-            .collect(),
+        program: Program::parse(&lines.join("\n")).unwrap().into(),
     })
 }
@@ -3,15 +3,9 @@ use super::vec2d::*;
 use super::Solution;
 use std::collections::HashMap;
 use std::collections::VecDeque;
-use std::sync::mpsc::*;
 
 type Map = HashMap<Vec2D, Tile>;
 
-enum Action {
-    Do((Compass, Vec2D)),
-    Undo((Compass, Vec2D)),
-}
-
 #[derive(Copy, Clone, Debug, PartialEq)]
 enum Tile {
     Wall,
@@ -45,43 +39,25 @@ fn next_pos(pos: Vec2D, pred: &dyn Fn(&Vec2D) -> bool) -> Vec<Vec2D> {
         .collect()
 }
 
-fn explore_map(program: &Vec<Intcode>) -> Map {
-    let (joystick, sink) = channel();
-    let droid = exec(program, sink, None);
+fn explore_map(program: &[Intcode]) -> Map {
     let mut map = HashMap::new();
-    let mut pos = Vec2D::default();
-    let mut stack = Vec::new();
-    loop {
+    let mut q = VecDeque::new();
+    map.insert(Vec2D::default(), Tile::Space);
+    q.push_back((Vec2D::default(), Vm::new(program)));
+    // Breadth-first search where every reachable position gets its own copy
+    // of the droid, so there is never any need to walk back
+    while let Some((pos, droid)) = q.pop_front() {
         for next_pos in next_pos(pos, &|p| !map.contains_key(p)) {
-            let dir = pos.compass(next_pos);
-            let undo = next_pos.compass(pos);
-            stack.push(Action::Undo((undo, pos)));
-            stack.push(Action::Do((dir, next_pos)));
-        }
-        if let Some(action) = stack.pop() {
-            match action {
-                Action::Do((compass, next_pos)) => {
-                    joystick.send(compass_to_intcode(compass)).unwrap();
-                    let tile = intcode_to_tile(droid.recv().unwrap());
-                    map.insert(next_pos, tile);
-                    if tile == Tile::Wall {
-                        stack.pop();
-                    } else {
-                        pos = next_pos;
-                    }
-                }
-                Action::Undo((compass, prev_pos)) => {
-                    joystick.send(compass_to_intcode(compass)).unwrap();
-                    if intcode_to_tile(droid.recv().unwrap()) != Tile::Space {
-                        panic!("Failed to undo move");
-                    }
-                    pos = prev_pos;
-                }
+            let mut droid = droid.clone();
+            droid.push_input(compass_to_intcode(pos.compass(next_pos)));
+            let tile = intcode_to_tile(droid.output().unwrap().unwrap());
+            map.insert(next_pos, tile);
+            if tile != Tile::Wall {
+                q.push_back((next_pos, droid));
             }
-        } else {
-            break map;
         }
     }
+    map
 }
 
 fn fill_map_with_oxygen(map: &mut Map) -> (u32, u32) {
@@ -131,9 +107,6 @@ pub struct Day15 {
 
 pub fn solution(lines: Vec<&str>) -> Box<dyn Solution> {
     Box::new(Day15 {
-        program: lines[0]
-            .split(",")
-            .map(|ic| ic.parse::<Intcode>().unwrap())
-            .collect(),
+        program: Program::parse(&lines.join("\n")).unwrap().into(),
     })
 }
@@ -2,10 +2,8 @@ use super::intcode::*;
 use super::vec2d::*;
 use super::Solution;
 use regex::Regex;
-use std::char;
 use std::collections::HashMap;
 use std::env;
-use std::sync::mpsc::channel;
 
 type Map = HashMap<Vec2D, Tile>;
 type Movement = (Turn, Distance);
@@ -18,27 +16,23 @@ enum Tile {
 }
 
 fn map_get(program: &Vec<Intcode>) -> Map {
-    let (_, sink) = channel();
-    let camera = exec(program, sink, None);
+    let mut camera = AsciiMachine::new(Vm::new(program));
     let mut map = Map::new();
-    let mut pos = Vec2D::default();
-    while let Ok(feed) = camera.recv() {
-        let camera_feedback = char::from_u32(feed as u32).unwrap();
-        match camera_feedback {
-            '#' => map.insert(pos, Tile::Scaffold),
-            '<' => map.insert(pos, Tile::Robot(Dir::Left)),
-            '>' => map.insert(pos, Tile::Robot(Dir::Right)),
-            '^' => map.insert(pos, Tile::Robot(Dir::Up)),
-            'v' => map.insert(pos, Tile::Robot(Dir::Down)),
-            '.' => map.insert(pos, Tile::Space),
-            '\n' => None,
-            _ => panic!("Invalid camera feedback"),
-        };
-        pos = if camera_feedback == '\n' {
-            Vec2D::from(0, pos.y() + 1)
-        } else {
-            pos + RIGHT
+    let mut y = 0;
+    while let Some(line) = camera.read_line().unwrap() {
+        for (x, camera_feedback) in line.chars().enumerate() {
+            let tile = match camera_feedback {
+                '#' => Tile::Scaffold,
+                '<' => Tile::Robot(Dir::Left),
+                '>' => Tile::Robot(Dir::Right),
+                '^' => Tile::Robot(Dir::Up),
+                'v' => Tile::Robot(Dir::Down),
+                '.' => Tile::Space,
+                _ => panic!("Invalid camera feedback"),
+            };
+            map.insert(Vec2D::from(x as Coord, y), tile);
         }
+        y += 1;
     }
     map
 }
@@ -174,27 +168,18 @@ impl Solution for Day17 {
 
     fn part2(&self) -> String {
         let map = map_get(&self.program);
-        let (input, sink) = channel();
-        let mut program = self.program.clone();
-        program[0] = 2;
-        let output = exec(&program, sink, None);
+        let mut vm = Vm::new(&self.program);
+        vm.poke(0, 2);
+        let mut robot = AsciiMachine::new(vm);
+        robot.echo(self.verbose);
 
         let path = path_to_string(path_get(&map));
         let (main_rtn, a, b, c) = compile(path);
-        for ch in main_rtn.chars().chain(
-            a.chars()
-                .chain(b.chars().chain(c.chars().chain("n\n".chars()))),
-        ) {
-            if self.verbose {
-                print!("{}", ch);
-            }
-            input.send(ch as i64).unwrap();
-        }
-        let mut dust = 0;
-        while let Ok(ch) = output.recv() {
-            dust = ch;
+        for routine in &[main_rtn, a, b, c, "n".to_string()] {
+            robot.send_line(routine.trim_end());
         }
-        dust.to_string()
+        robot.read_all().unwrap();
+        robot.take_value().unwrap().to_string()
     }
 }
 
@@ -206,10 +191,7 @@ pub struct Day17 {
 
 pub fn solution(lines: Vec<&str>) -> Box<dyn Solution> {
     Box::new(Day17 {
-        program: lines[0]
-            .split(",")
-            .map(|ic| ic.parse::<Intcode>().unwrap())
-            .collect(),
+        program: Program::parse(&lines.join("\n")).unwrap().into(),
         verbose: env::args().last().unwrap() == "-v",
     })
 }
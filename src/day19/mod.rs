@@ -1,14 +1,12 @@
-use std::sync::mpsc::*;
 use super::intcode::*;
 use super::Solution;
 use super::vec2d::*;
 
-fn is_in_beam(pos: Vec2D, program: &Vec<Intcode>) -> bool {
-    let (input, sink) = channel();
-    let output = exec(program, sink, None);
-    input.send(pos.x() as Intcode).unwrap();
-    input.send(pos.y() as Intcode).unwrap();
-    output.recv().unwrap() != 0
+fn is_in_beam(pos: Vec2D, program: &[Intcode]) -> bool {
+    let mut drone = Vm::new(program);
+    drone.push_input(pos.x() as Intcode);
+    drone.push_input(pos.y() as Intcode);
+    drone.output().unwrap().unwrap() != 0
 }
 
 impl Solution for Day19 {
@@ -49,9 +47,6 @@ pub struct Day19 {
 
 pub fn solution(lines: Vec<&str>) -> Box<dyn Solution> {
     Box::new(Day19 {
-        program: lines[0]
-            .split(",")
-            .map(|ic| ic.parse::<Intcode>().unwrap())
-            .collect(),
+        program: Program::parse(&lines.join("\n")).unwrap().into(),
     })
 }
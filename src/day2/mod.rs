@@ -1,57 +1,20 @@
+use super::intcode::*;
 use super::Solution;
-use Instruction::*;
-
-type Intcode = usize;
-type Addr = Intcode;
-
-#[derive(PartialEq, Debug)]
-enum Instruction {
-    Add(Addr, Addr, Addr),
-    Mul(Addr, Addr, Addr),
-    Halt,
-}
 
 // State required to solve day 2
 pub struct Day2 {
     memory: Vec<Intcode>,
 }
 
-fn fetch(mem: &Vec<Intcode>, ip: Addr) -> Instruction {
-    let opcode = mem[ip];
-    match opcode {
-        1 => Add(mem[ip + 1], mem[ip + 2], mem[ip + 3]),
-        2 => Mul(mem[ip + 1], mem[ip + 2], mem[ip + 3]),
-        99 => Halt,
-        _ => panic!("Invalid opcode"),
-    }
-}
-
-fn exec(instr: Instruction, mem: &mut Vec<Intcode>, ip: &mut Addr) -> bool {
-    let mut new_ip = *ip + 4;
-    match instr {
-        Add(src0, src1, dst) => mem[dst] = mem[src0] + mem[src1],
-        Mul(src0, src1, dst) => mem[dst] = mem[src0] * mem[src1],
-        Halt => new_ip = *ip,
-    };
-    *ip = new_ip;
-    instr == Halt
-}
-
-fn run(memory: &Vec<Intcode>, noun: Intcode, verb: Intcode) -> Intcode {
-    let mut mem = memory.clone();
-    let mut ip: Addr = 0;
+fn run(memory: &[Intcode], noun: Intcode, verb: Intcode) -> Intcode {
+    let mut vm = Vm::new(memory);
     // The value placed in address 1 is called the noun, and the value
     // placed in address 2 is called the verb
-    mem[1] = noun;
-    mem[2] = verb;
-    loop {
-        let instr = fetch(&mem, ip);
-        if exec(instr, &mut mem, &mut ip) {
-            break;
-        }
-    }
+    vm.poke(1, noun);
+    vm.poke(2, verb);
+    assert_eq!(vm.run(), Ok(Event::Halted));
     // Output is value at position 0 after the program halts.
-    mem[0]
+    vm.peek(0)
 }
 
 impl Solution for Day2 {
@@ -79,10 +42,7 @@ impl Solution for Day2 {
 
 pub fn solution(lines: Vec<&str>) -> Box<dyn Solution> {
     Box::new(Day2 {
-        memory: lines[0]
-            .split(",")
-            .map(|ic| ic.parse::<Intcode>().unwrap())
-            .collect(),
+        memory: Program::parse(&lines.join("\n")).unwrap().into(),
     })
 }
 
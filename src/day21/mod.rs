@@ -1,7 +1,6 @@
 use super::intcode::*;
 use super::Solution;
 use std::env;
-use std::sync::mpsc::*;
 
 const SCENARIO_LEN: i32 = 17; // From description
 
@@ -153,36 +152,26 @@ fn get_hull_damage(program: &Vec<Intcode>, extended_range: bool, verbose: bool)
                     .all(|scenario| test_scenario(scenario, script))
             });
             if let Some(candidate) = candidates.next() {
-                let (input, sink) = channel();
-                let output = exec(&program.clone(), sink, None);
-                for ch in script_to_string(candidate, extended_range).chars() {
-                    input.send(ch as Intcode).unwrap();
+                let mut droid = AsciiMachine::new(Vm::new(program));
+                for line in script_to_string(candidate, extended_range).lines() {
+                    droid.send_line(line);
                 }
-                loop {
-                    let maybe_hull_damage = output.recv().unwrap();
-                    if maybe_hull_damage > 127 {
-                        if verbose {
-                            println!("Scenarios needed : {}", scenarios.len());
-                            println!("{}", script_to_string(candidate, extended_range));
-                        }
-                        return maybe_hull_damage as u32;
-                    }
-                    if maybe_hull_damage == '#' as Intcode {
-                        break;
+                let report = droid.read_all().unwrap();
+                if let Some(hull_damage) = droid.take_value() {
+                    if verbose {
+                        println!("Scenarios needed : {}", scenarios.len());
+                        println!("{}", script_to_string(candidate, extended_range));
                     }
+                    return hull_damage as u32;
                 }
+                // Holes in the hull the droid fell through make up a new scenario
+                let hull = report.lines().find(|line| line.starts_with('#')).unwrap();
                 let mut new_scenario = 0;
-                let mut idx = 1;
-                while let Ok(tile) = output.recv() {
-                    if tile == '\n' as Intcode {
-                        break;
-                    }
-                    if tile == '.' as Intcode {
+                for (idx, tile) in hull.chars().enumerate().skip(1) {
+                    if tile == '.' {
                         new_scenario |= 1 << idx;
                     }
-                    idx += 1;
                 }
-                while let Ok(_) = output.recv() {}
                 scenarios.push(!new_scenario);
             } else {
                 break;
@@ -210,10 +199,7 @@ pub struct Day21 {
 
 pub fn solution(lines: Vec<&str>) -> Box<dyn Solution> {
     Box::new(Day21 {
-        program: lines[0]
-            .split(",")
-            .map(|ic| ic.parse::<Intcode>().unwrap())
-            .collect(),
+        program: Program::parse(&lines.join("\n")).unwrap().into(),
         verbose: env::args().last().unwrap() == "-v",
     })
 }
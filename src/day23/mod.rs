@@ -1,76 +1,74 @@
 use super::intcode::*;
 use super::Solution;
 use std::env;
-use std::sync::mpsc::channel;
 
-impl Solution for Day23 {
-    fn part1(&self) -> String {
-        let mut nics = Vec::new();
-        for addr in 0..50 {
-            let (input, sink) = channel();
-            let output = exec(&self.program, sink, None);
-            input.send(addr as Intcode).unwrap();
-            nics.push((input, output));
+const NUM_NICS: usize = 50;
+
+// Stops the network as soon as a packet is sent to the NAT
+struct FirstPacket;
+
+impl Nat for FirstPacket {
+    fn receive(&mut self, packet: Packet) -> Verdict {
+        Verdict::Stop(packet.y)
+    }
+
+    fn idle(&mut self) -> Verdict {
+        Verdict::Continue
+    }
+}
+
+// Wakes up the network by sending the last packet it received to address 0,
+// stops when it is about to send the same Y value twice in a row
+struct Wakeup {
+    last: Option<Packet>,
+    last_sent_y: Option<Intcode>,
+    verbose: bool,
+}
+
+impl Nat for Wakeup {
+    fn receive(&mut self, packet: Packet) -> Verdict {
+        self.last = Some(packet);
+        Verdict::Continue
+    }
+
+    fn idle(&mut self) -> Verdict {
+        let packet = match self.last {
+            Some(packet) => packet,
+            None => return Verdict::Continue,
+        };
+        if self.verbose {
+            println!("{}", packet.y);
         }
-        'net: loop {
-            for nic in &nics {
-                if let Ok(dst) = nic.1.try_recv() {
-                    let x = nic.1.recv().unwrap();
-                    let y = nic.1.recv().unwrap();
-                    if dst == 255 {
-                        break 'net y;
-                    }
-                    nics[dst as usize].0.send(x).unwrap();
-                    nics[dst as usize].0.send(y).unwrap();
-                } else {
-                    nic.0.send(-1).unwrap();
-                }
-            }
+        if self.last_sent_y == Some(packet.y) {
+            return Verdict::Stop(packet.y);
         }
-        .to_string()
+        self.last_sent_y = Some(packet.y);
+        Verdict::Send(Packet {
+            src: 255,
+            dst: 0,
+            ..packet
+        })
+    }
+}
+
+impl Solution for Day23 {
+    fn part1(&self) -> String {
+        Network::new(&self.program, NUM_NICS)
+            .run(&mut FirstPacket)
+            .unwrap()
+            .to_string()
     }
 
     fn part2(&self) -> String {
-        let mut nics = Vec::new();
-        for addr in 0..50 {
-            let (input, sink) = channel();
-            let output = exec(&self.program, sink, None);
-            input.send(addr as Intcode).unwrap();
-            nics.push((input, output));
-        }
-        let mut nat_x = -1;
-        let mut nat_y = -1;
-        'net: loop {
-            let mut is_idle = true;
-            for nic in nics.iter() {
-                if let Ok(dst) = nic.1.recv_timeout(std::time::Duration::from_micros(100)) {
-                    let x = nic.1.recv().unwrap();
-                    let y = nic.1.recv().unwrap();
-                    if dst == 255 {
-                        if self.verbose {
-                            println!("{}", y);
-                        }
-                        if nat_y == y {
-                            break 'net y;
-                        }
-                        nat_x = x;
-                        nat_y = y;
-                    } else {
-                        is_idle = false;
-                        nics[dst as usize].0.send(x).unwrap();
-                        nics[dst as usize].0.send(y).unwrap();
-                    }
-                } else {
-                    nic.0.send(-1).unwrap();
-                }
-            }
-            if is_idle {
-                nics[0].0.send(nat_x).unwrap();
-                nics[0].0.send(nat_y).unwrap();
-                std::thread::sleep(std::time::Duration::from_micros(1));
-            }
-        }
-        .to_string()
+        let mut nat = Wakeup {
+            last: None,
+            last_sent_y: None,
+            verbose: self.verbose,
+        };
+        Network::new(&self.program, NUM_NICS)
+            .run(&mut nat)
+            .unwrap()
+            .to_string()
     }
 }
 
@@ -82,10 +80,7 @@ pub struct Day23 {
 
 pub fn solution(lines: Vec<&str>) -> Box<dyn Solution> {
     Box::new(Day23 {
-        program: lines[0]
-            .split(",")
-            .map(|ic| ic.parse::<Intcode>().unwrap())
-            .collect(),
+        program: Program::parse(&lines.join("\n")).unwrap().into(),
         verbose: env::args().last().unwrap() == "-v",
     })
 }
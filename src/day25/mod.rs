@@ -4,40 +4,26 @@ use super::Solution;
 use std::collections::HashSet;
 use std::env;
 use std::iter::FromIterator;
-use std::sync::mpsc::*;
 
 type Room = String;
 type Map = HashSet<(Room, Compass)>;
 type Exits = Vec<Compass>;
 type Item = String;
 type Items = Vec<Item>;
-type ItemBag = u32;
 
+#[derive(Clone)]
 struct Robot {
-    carrying: ItemBag,
     seen: Items,
     last_move: Option<Compass>,
     checkpoint_found: bool,
     path_to_checkpoint: Vec<Compass>,
-    input: Sender<Intcode>,
-    output: Receiver<Intcode>,
-    verbose: bool,
+    droid: AsciiMachine,
 }
 
-fn room_info_lines(output: &Receiver<Intcode>, verbose: bool) -> Vec<String> {
-    let mut desc_buf = Vec::new();
-    while let Ok(ch) = output.recv() {
-        desc_buf.push((ch as u8) as char);
-        if desc_buf.iter().collect::<String>().ends_with("Command?")
-        {
-            break;
-        }
-    }
-    let desc_str = desc_buf.iter().collect::<String>();
-    if verbose {
-        println!("{}", desc_str);
-    }
-    desc_str
+fn room_info_lines(droid: &mut AsciiMachine) -> Vec<String> {
+    droid
+        .read_until("Command?")
+        .unwrap()
         .trim()
         .split('\n')
         .map(|s| s.to_string())
@@ -90,33 +76,24 @@ fn item_get(lines: &Vec<String>) -> Option<Item> {
     item
 }
 
-fn room_info(output: &Receiver<Intcode>, verbose: bool) -> (Room, Exits, Option<Item>) {
-    let lines = room_info_lines(output, verbose);
+fn room_info(droid: &mut AsciiMachine) -> (Room, Exits, Option<Item>) {
+    let lines = room_info_lines(droid);
     (name_get(&lines), exits_get(&lines), item_get(&lines))
 }
 
-fn issue_cmd(cmd: String, input: &Sender<Intcode>, verbose: bool) {
-    for ch in cmd.chars() {
-        input.send(ch as Intcode).unwrap();
-        if verbose {
-            print!("{}", ch);
-        }
-    }
-}
-
 impl Robot {
     fn item_pick(&mut self, item: &Item) {
-        issue_cmd("take ".to_string() + item + "\n", &self.input, self.verbose);
-        self.carrying |= 1 << self.seen.len();
+        self.droid.send_line(&format!("take {}", item));
         self.seen.push(item.clone());
     }
 
     fn item_drop(&mut self, item: &Item) {
-        issue_cmd("drop ".to_string() + item + "\n", &self.input, self.verbose);
+        self.droid.send_line(&format!("drop {}", item));
     }
 
     fn move_to(&mut self, compass: Compass, new_area: bool) {
-        issue_cmd(format!("{:?}\n", compass).to_lowercase(), &self.input, self.verbose);
+        self.droid
+            .send_line(&format!("{:?}", compass).to_lowercase());
         self.last_move = Some(compass);
         if !self.checkpoint_found {
             if new_area {
@@ -143,7 +120,7 @@ fn gather_items(robot: &mut Robot) {
     let mut visited = Map::new();
     let mut stack = Vec::new();
     loop {
-        let (name, exits, item) = room_info(&robot.output, robot.verbose);
+        let (name, exits, item) = room_info(&mut robot.droid);
         if exits.len() > 0 {
             if let Some(last_move) = robot.last_move {
                 visited.insert((name.clone(), last_move.mirror()));
@@ -177,30 +154,25 @@ fn gather_items(robot: &mut Robot) {
 fn go_to_security_checkpoint(robot: &mut Robot) {
     for &compass in &robot.path_to_checkpoint.clone() {
         robot.move_to(compass, false);
-        room_info(&robot.output, robot.verbose);
+        room_info(&mut robot.droid);
     }
 }
 
-fn password_get(robot: &mut Robot) -> String {
-    let all_items = robot.carrying;
-    let num_items = all_items.count_ones();
-    let item_names = robot.seen.clone();
-    for items in 0..=all_items {
-        robot.seen.clear();
-        for item in 0..num_items {
-            if (robot.carrying ^ items) & (1 << item) != 0 {
-                if items & (1 << item) != 0 {
-                    robot.item_pick(&item_names[item as usize]);
-                } else {
-                    robot.item_drop(&item_names[item as usize]);
-                }
-                room_info(&robot.output, robot.verbose);
+// Tries every combination of items on a copy of the robot standing at the
+// security checkpoint while carrying everything it found
+fn password_get(robot: &Robot) -> String {
+    let num_items = robot.seen.len();
+    for items in 0..(1u32 << num_items) {
+        let mut probe = robot.clone();
+        for (item, name) in robot.seen.iter().enumerate() {
+            if items & (1 << item) == 0 {
+                probe.item_drop(name);
+                room_info(&mut probe.droid);
             }
         }
-        robot.carrying = items;
-        robot.move_to(robot.last_move.unwrap(), false);
-        let resp = room_info_lines(&robot.output, robot.verbose).join("\n");
-        if resp.find("Alert!") == None {
+        probe.move_to(probe.last_move.unwrap(), false);
+        let resp = room_info_lines(&mut probe.droid).join("\n");
+        if !resp.contains("Alert!") {
             return resp.chars().filter(|c| c.is_numeric()).collect();
         }
     }
@@ -209,21 +181,17 @@ fn password_get(robot: &mut Robot) -> String {
 
 impl Solution for Day25 {
     fn part1(&self) -> String {
-        let (input, sink) = channel();
-        let output = exec(&self.program, sink, None);
         let mut robot = Robot {
-            carrying: 0,
             last_move: None,
             seen: Items::new(),
             checkpoint_found: false,
             path_to_checkpoint: Vec::new(),
-            input,
-            output,
-            verbose: self.verbose,
+            droid: AsciiMachine::new(Vm::new(&self.program)),
         };
+        robot.droid.echo(self.verbose);
         gather_items(&mut robot);
         go_to_security_checkpoint(&mut robot);
-        password_get(&mut robot)
+        password_get(&robot)
     }
 
     fn part2(&self) -> String {
@@ -239,10 +207,7 @@ pub struct Day25 {
 
 pub fn solution(lines: Vec<&str>) -> Box<dyn Solution> {
     Box::new(Day25 {
-        program: lines[0]
-            .split(",")
-            .map(|ic| ic.parse::<Intcode>().unwrap())
-            .collect(),
+        program: Program::parse(&lines.join("\n")).unwrap().into(),
         verbose: env::args().last().unwrap() == "-v",
     })
 }
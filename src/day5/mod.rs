@@ -1,38 +1,32 @@
 use super::intcode::*;
 use super::Solution;
-use std::sync::mpsc::*;
 
 // State required to solve day 5
 pub struct Day5 {
     memory: Vec<Intcode>,
 }
 
+// Runs the program until it halts and returns all output
+fn run(program: &[Intcode], input: &[Intcode]) -> Vec<Intcode> {
+    let mut output = Vec::new();
+    let event = Vm::new(program).run_io(&mut input.iter().cloned(), &mut output);
+    assert_eq!(event, Ok(Event::Halted));
+    output
+}
+
 impl Solution for Day5 {
     fn part1(&self) -> String {
-        let (input, sink) = channel();
-        let output = exec(&self.memory, sink, None);
-        input.send(1).unwrap();
-        let mut final_result = 0;
-        while let Ok(result) = output.recv() {
-            final_result = result;
-        }
-        final_result.to_string()
+        run(&self.memory, &[1]).last().unwrap().to_string()
     }
 
     fn part2(&self) -> String {
-        let (input, sink) = channel();
-        let output = exec(&self.memory, sink, None);
-        input.send(5).unwrap();
-        output.recv().unwrap().to_string()
+        run(&self.memory, &[5])[0].to_string()
     }
 }
 
 pub fn solution(lines: Vec<&str>) -> Box<dyn Solution> {
     Box::new(Day5 {
-        memory: lines[0]
-            .split(",")
-            .map(|ic| ic.parse::<Intcode>().unwrap())
-            .collect(),
+        memory: Program::parse(&lines.join("\n")).unwrap().into(),
     })
 }
 
@@ -42,121 +36,90 @@ mod tests {
 
     #[test]
     fn d5_ex1() {
-        let (input, sink) = channel();
-        input.send(42).unwrap();
-        let output = exec(&vec![3, 0, 4, 0, 99], sink, None);
-        assert_eq!(output.recv().unwrap(), 42);
+        assert_eq!(run(&[3, 0, 4, 0, 99], &[42]), vec![42]);
     }
 
     #[test]
     fn d5_ex2() {
-        let (_, sink) = channel();
-        let output = exec(&vec![1102, 11, 22, 7, 4, 7, 99, 0], sink, None);
-        assert_eq!(output.recv().unwrap(), 242);
+        assert_eq!(run(&[1102, 11, 22, 7, 4, 7, 99, 0], &[]), vec![242]);
     }
 
     #[test]
     fn d5_ex3() {
-        let (input, sink) = channel();
-        input.send(8).unwrap();
-        let output = exec(&vec![3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8], sink, None);
-        assert_eq!(output.recv().unwrap(), 1);
+        assert_eq!(run(&[3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8], &[8]), vec![1]);
     }
 
     #[test]
     fn d5_ex4() {
-        let (input, sink) = channel();
-        input.send(7).unwrap();
-        let output = exec(&vec![3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8], sink, None);
-        assert_eq!(output.recv().unwrap(), 0);
+        assert_eq!(run(&[3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8], &[7]), vec![0]);
     }
 
     #[test]
     fn d5_ex5() {
-        let (input, sink) = channel();
-        input.send(8).unwrap();
-        let output = exec(&vec![3, 9, 7, 9, 10, 9, 4, 9, 99, -1, 8], sink, None);
-        assert_eq!(output.recv().unwrap(), 0);
+        assert_eq!(run(&[3, 9, 7, 9, 10, 9, 4, 9, 99, -1, 8], &[8]), vec![0]);
     }
 
     #[test]
     fn d5_ex6() {
-        let (input, sink) = channel();
-        input.send(7).unwrap();
-        let output = exec(&vec![3, 9, 7, 9, 10, 9, 4, 9, 99, -1, 8], sink, None);
-        assert_eq!(output.recv().unwrap(), 1);
+        assert_eq!(run(&[3, 9, 7, 9, 10, 9, 4, 9, 99, -1, 8], &[7]), vec![1]);
     }
 
     #[test]
     fn d5_ex7() {
-        let (input, sink) = channel();
-        input.send(8).unwrap();
-        let output = exec(&vec![3, 3, 1108, -1, 8, 3, 4, 3, 99], sink, None);
-        assert_eq!(output.recv().unwrap(), 1);
+        assert_eq!(run(&[3, 3, 1108, -1, 8, 3, 4, 3, 99], &[8]), vec![1]);
     }
 
     #[test]
     fn d5_ex8() {
-        let (input, sink) = channel();
-        input.send(7).unwrap();
-        let output = exec(&vec![3, 3, 1108, -1, 8, 3, 4, 3, 99], sink, None);
-        assert_eq!(output.recv().unwrap(), 0);
+        assert_eq!(run(&[3, 3, 1108, -1, 8, 3, 4, 3, 99], &[7]), vec![0]);
     }
 
     #[test]
     fn d5_ex9() {
-        let (input, sink) = channel();
-        input.send(8).unwrap();
-        let output = exec(&vec![3, 3, 1107, -1, 8, 3, 4, 3, 99], sink, None);
-        assert_eq!(output.recv().unwrap(), 0);
+        assert_eq!(run(&[3, 3, 1107, -1, 8, 3, 4, 3, 99], &[8]), vec![0]);
     }
 
     #[test]
     fn d5_ex10() {
-        let (input, sink) = channel();
-        input.send(7).unwrap();
-        let output = exec(&vec![3, 3, 1107, -1, 8, 3, 4, 3, 99], sink, None);
-        assert_eq!(output.recv().unwrap(), 1);
+        assert_eq!(run(&[3, 3, 1107, -1, 8, 3, 4, 3, 99], &[7]), vec![1]);
     }
 
     #[test]
     fn d5_ex11() {
-        let (input, sink) = channel();
-        input.send(0).unwrap();
-        let output = exec(
-            &vec![3, 12, 6, 12, 15, 1, 13, 14, 13, 4, 13, 99, -1, 0, 1, 9],
-            sink,
-            None,
+        assert_eq!(
+            run(
+                &[3, 12, 6, 12, 15, 1, 13, 14, 13, 4, 13, 99, -1, 0, 1, 9],
+                &[0]
+            ),
+            vec![0]
         );
-        assert_eq!(output.recv().unwrap(), 0);
     }
 
     #[test]
     fn d5_ex12() {
-        let (input, sink) = channel();
-        input.send(13).unwrap();
-        let output = exec(
-            &vec![3, 12, 6, 12, 15, 1, 13, 14, 13, 4, 13, 99, -1, 0, 1, 9],
-            sink,
-            None,
+        assert_eq!(
+            run(
+                &[3, 12, 6, 12, 15, 1, 13, 14, 13, 4, 13, 99, -1, 0, 1, 9],
+                &[13]
+            ),
+            vec![1]
         );
-        assert_eq!(output.recv().unwrap(), 1);
     }
 
     #[test]
     fn d5_ex13() {
-        let (input, sink) = channel();
-        input.send(0).unwrap();
-        let output = exec(&vec![3, 3, 1105, -1, 9, 1101, 0, 0, 12, 4, 12, 99, 1], sink, None);
-        assert_eq!(output.recv().unwrap(), 0);
+        assert_eq!(
+            run(&[3, 3, 1105, -1, 9, 1101, 0, 0, 12, 4, 12, 99, 1], &[0]),
+            vec![0]
+        );
     }
 
     #[test]
     fn d5_ex14() {
-        let (input, sink) = channel();
-        input.send(13).unwrap();
-        let output = exec(&vec![3, 3, 1105, -1, 9, 1101, 0, 0, 12, 4, 12, 99, 1], sink, None);
-        assert_eq!(output.recv().unwrap(), 1);
+        assert_eq!(
+            run(&[3, 3, 1105, -1, 9, 1101, 0, 0, 12, 4, 12, 99, 1], &[13]),
+            vec![1]
+        );
     }
 
     #[test]
@@ -166,10 +129,7 @@ mod tests {
             0, 1002, 21, 125, 20, 4, 20, 1105, 1, 46, 104, 999, 1105, 1, 46, 1101, 1000, 1, 20, 4,
             20, 1105, 1, 46, 98, 99,
         ];
-        let (input, sink) = channel();
-        input.send(5).unwrap();
-        let output = exec(&memory, sink, None);
-        assert_eq!(output.recv().unwrap(), 999);
+        assert_eq!(run(&memory, &[5]), vec![999]);
     }
 
     #[test]
@@ -179,10 +139,7 @@ mod tests {
             0, 1002, 21, 125, 20, 4, 20, 1105, 1, 46, 104, 999, 1105, 1, 46, 1101, 1000, 1, 20, 4,
             20, 1105, 1, 46, 98, 99,
         ];
-        let (input, sink) = channel();
-        input.send(8).unwrap();
-        let output = exec(&memory, sink, None);
-        assert_eq!(output.recv().unwrap(), 1000);
+        assert_eq!(run(&memory, &[8]), vec![1000]);
     }
 
     #[test]
@@ -192,10 +149,7 @@ mod tests {
             0, 1002, 21, 125, 20, 4, 20, 1105, 1, 46, 104, 999, 1105, 1, 46, 1101, 1000, 1, 20, 4,
             20, 1105, 1, 46, 98, 99,
         ];
-        let (input, sink) = channel();
-        input.send(17).unwrap();
-        let output = exec(&memory, sink, None);
-        assert_eq!(output.recv().unwrap(), 1001);
+        assert_eq!(run(&memory, &[17]), vec![1001]);
     }
 
     const INPUT: &str =
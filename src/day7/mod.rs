@@ -1,47 +1,74 @@
-use permutohedron::LexicalPermutation;
-use std::sync::mpsc::*;
 use super::intcode::*;
 use super::Solution;
+use permutohedron::LexicalPermutation;
+use std::thread;
 
-const NUM_AMPS: usize = 5;
-
-fn amplifiers(program: &Vec<Intcode>, phases: &[Intcode; NUM_AMPS]) -> Intcode {
-    let (input, sink) = channel();
-    let mut output = sink;
-    input.send(phases[0]).unwrap();
-    for i in 1..NUM_AMPS {
-        output = exec(program, output, Some(phases[i]))
-    }
-    output = exec(program, output, None);
-    let mut final_thrust = 0;
-    input.send(0).unwrap();
-    while let Ok(thrust) = output.recv() {
-        final_thrust = thrust;
-        if let Err(_) = input.send(thrust) {
-            break;
-        }
-    }
-    final_thrust
+// Amplifiers connected in a ring, each booted with its phase setting, the
+// first one is also given the input signal 0. Amplifiers that don't use the
+// feedback loop halt after passing on their signal. Returns the last signal
+// output by the last amplifier.
+fn amplifiers(program: &[Intcode], phases: &[Intcode]) -> Intcode {
+    let mut circuit = Circuit::new(program);
+    let amps = phases
+        .iter()
+        .enumerate()
+        .map(|(i, &phase)| match i {
+            0 => circuit.add(&[phase, 0]),
+            _ => circuit.add(&[phase]),
+        })
+        .collect::<Vec<_>>();
+    circuit.ring(&amps);
+    circuit.output(*amps.last().unwrap());
+    *circuit.run().unwrap().last().unwrap()
 }
 
-fn exec_with_phases(program: &Vec<Intcode>, phases: &mut [Intcode; NUM_AMPS]) -> Intcode {
-    let mut thrusters_signal = Vec::new();
+// Tries every order of the phase settings, the orders are shared out among
+// one worker thread per CPU. Returns the highest signal and the phase settings
+// giving it.
+fn exec_with_phases(program: &[Intcode], phases: &[Intcode]) -> (Intcode, Vec<Intcode>) {
+    let mut phases = phases.to_vec();
+    phases.sort_unstable();
+    let mut settings = Vec::new();
     loop {
-        thrusters_signal.push(amplifiers(program, phases));
+        settings.push(phases.clone());
         if !phases.next_permutation() {
             break;
         }
     }
-    thrusters_signal.into_iter().max().unwrap()
+    let workers = thread::available_parallelism().map_or(1, |n| n.get());
+    thread::scope(|scope| {
+        let searches = settings
+            .chunks(settings.len().div_ceil(workers))
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|phases| (amplifiers(program, phases), phases))
+                        .max_by_key(|(signal, _)| *signal)
+                })
+            })
+            .collect::<Vec<_>>();
+        searches
+            .into_iter()
+            .filter_map(|search| search.join().unwrap())
+            .max_by_key(|(signal, _)| *signal)
+            .map(|(signal, phases)| (signal, phases.clone()))
+            .unwrap()
+    })
+}
+
+fn report((signal, phases): (Intcode, Vec<Intcode>)) -> String {
+    let phases = phases.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+    format!("{} with phases {}", signal, phases.join(","))
 }
 
 impl Solution for Day7 {
     fn part1(&self) -> String {
-        exec_with_phases(&self.memory, &mut [0, 1, 2, 3, 4]).to_string()
+        report(exec_with_phases(&self.memory, &[0, 1, 2, 3, 4]))
     }
 
     fn part2(&self) -> String {
-        exec_with_phases(&self.memory, &mut [5, 6, 7, 8, 9]).to_string()
+        report(exec_with_phases(&self.memory, &[5, 6, 7, 8, 9]))
     }
 }
 
@@ -52,10 +79,7 @@ pub struct Day7 {
 
 pub fn solution(lines: Vec<&str>) -> Box<dyn Solution> {
     Box::new(Day7 {
-        memory: lines[0]
-            .split(",")
-            .map(|ic| ic.parse::<Intcode>().unwrap())
-            .collect(),
+        memory: Program::parse(&lines.join("\n")).unwrap().into(),
     })
 }
 
@@ -102,6 +126,22 @@ mod tests {
         assert_eq!(amplifiers(&memory, &phases), 139629729);
     }
 
+    #[test]
+    fn d7_best_phases() {
+        let memory = vec![
+            3, 15, 3, 16, 1002, 16, 10, 16, 1, 16, 15, 15, 4, 15, 99, 0, 0,
+        ];
+        let best = exec_with_phases(&memory, &[0, 1, 2, 3, 4]);
+        assert_eq!(best, (43210, vec![4, 3, 2, 1, 0]));
+        assert_eq!(report(best), "43210 with phases 4,3,2,1,0");
+        let memory = vec![
+            3, 26, 1001, 26, -4, 26, 3, 27, 1002, 27, 2, 27, 1, 27, 26, 27, 4, 27, 1001, 28, -1,
+            28, 1005, 28, 6, 99, 0, 0, 5,
+        ];
+        let best = exec_with_phases(&memory, &[5, 6, 7, 8, 9]);
+        assert_eq!(best, (139629729, vec![9, 8, 7, 6, 5]));
+    }
+
     #[test]
     fn d7_ex5() {
         let memory = vec![
@@ -1,20 +1,21 @@
 use super::intcode::*;
 use super::Solution;
-use std::sync::mpsc::*;
+
+// Runs the BOOST program until it halts and returns all output
+fn boost(program: &[Intcode], input: &[Intcode]) -> Vec<Intcode> {
+    let mut output = Vec::new();
+    let event = Vm::new(program).run_io(&mut input.iter().cloned(), &mut output);
+    assert_eq!(event, Ok(Event::Halted));
+    output
+}
 
 impl Solution for Day9 {
     fn part1(&self) -> String {
-        let (input, sink) = channel();
-        let output = exec(&self.program, sink, None);
-        input.send(1).unwrap();
-        output.recv().unwrap().to_string()
+        boost(&self.program, &[1])[0].to_string()
     }
 
     fn part2(&self) -> String {
-        let (input, sink) = channel();
-        let output = exec(&self.program, sink, None);
-        input.send(2).unwrap();
-        output.recv().unwrap().to_string()
+        boost(&self.program, &[2])[0].to_string()
     }
 }
 
@@ -25,10 +26,7 @@ pub struct Day9 {
 
 pub fn solution(lines: Vec<&str>) -> Box<dyn Solution> {
     Box::new(Day9 {
-        program: lines[0]
-            .split(",")
-            .map(|ic| ic.parse::<Intcode>().unwrap())
-            .collect(),
+        program: Program::parse(&lines.join("\n")).unwrap().into(),
     })
 }
 
@@ -41,29 +39,19 @@ mod tests {
         let program = vec![
             109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
         ];
-        let (_, sink) = channel();
-        let output = exec(&program, sink, None);
-        let mut result = Vec::new();
-        while let Ok(r) = output.recv() {
-            result.push(r);
-        }
-        assert_eq!(program, result);
+        assert_eq!(boost(&program, &[]), program);
     }
 
     #[test]
     fn d9_ex2() {
         let program = vec![1102, 34915192, 34915192, 7, 4, 7, 99, 0];
-        let (_, sink) = channel();
-        let output = exec(&program, sink, None);
-        let result = output.recv().unwrap();
+        let result = boost(&program, &[])[0];
         assert_eq!(16, result.to_string().chars().count());
     }
 
     #[test]
     fn d9_ex3() {
         let program = vec![104, 1125899906842624, 99];
-        let (_, sink) = channel();
-        let output = exec(&program, sink, None);
-        assert_eq!(1125899906842624, output.recv().unwrap());
+        assert_eq!(boost(&program, &[]), vec![1125899906842624]);
     }
 }
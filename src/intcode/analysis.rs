@@ -0,0 +1,381 @@
+use super::disasm::{decode, Op, Operand};
+use super::*;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+// Where execution may continue after the last instruction of a basic block.
+// Jumps to addresses that are only known at run time are Unknown.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Edge {
+    Jump(Intcode),
+    Next(Intcode),
+    Unknown,
+}
+
+// Instructions that are always executed in sequence, only the first one is the
+// target of a jump
+#[derive(Clone, Debug, PartialEq)]
+pub struct Block {
+    pub ops: Vec<(Intcode, Op)>,
+    pub edges: Vec<Edge>,
+}
+
+// Words never reached as code, with the number of instructions referring to
+// them by address
+#[derive(Clone, Debug, PartialEq)]
+struct Region {
+    start: Intcode,
+    len: usize,
+    refs: usize,
+}
+
+// Result of a static analysis of a program image. Code is found by following
+// all jumps with known targets from address 0. Intcode has no call
+// instruction, so return addresses pushed on the stack are also followed.
+// Those are constants stored relative to the relative base that point right
+// after a jump instruction, they are said to have their address taken.
+pub struct Analysis {
+    blocks: BTreeMap<Intcode, Block>,
+    taken: BTreeSet<Intcode>,
+    invalid: BTreeSet<Intcode>,
+    writes: Vec<(Intcode, Intcode)>,
+    regions: Vec<Region>,
+}
+
+impl Block {
+    // Address right after the last instruction
+    pub fn end(&self) -> Intcode {
+        let (addr, op) = self.ops.last().unwrap();
+        addr + op.num_words() as Intcode
+    }
+}
+
+fn decode_at(program: &[Intcode], addr: Intcode) -> Option<Op> {
+    if addr < 0 || addr as usize >= program.len() {
+        return None;
+    }
+    decode(program, addr as usize)
+}
+
+fn is_jump(op: &Op) -> bool {
+    matches!(op, Op::Instr(JmpIfTrue, _) | Op::Instr(JmpIfFalse, _))
+}
+
+fn successors(addr: Intcode, op: &Op) -> Vec<Edge> {
+    let next = Edge::Next(addr + op.num_words() as Intcode);
+    match op {
+        Op::Instr(Halt, _) => vec![],
+        Op::Instr(instr, operands) if is_jump(op) => {
+            let target = match operands[1] {
+                Operand::Immediate(target) => Edge::Jump(target),
+                _ => Edge::Unknown,
+            };
+            match operands[0] {
+                Operand::Immediate(cond) if (cond != 0) == (*instr == JmpIfTrue) => vec![target],
+                Operand::Immediate(_) => vec![next],
+                _ => vec![target, next],
+            }
+        }
+        _ => vec![next],
+    }
+}
+
+// Address right after a jump instruction, where a call returns to
+fn is_return_site(program: &[Intcode], addr: Intcode) -> bool {
+    decode_at(program, addr - 3).is_some_and(|op| is_jump(&op))
+        && decode_at(program, addr).is_some()
+}
+
+// Constant stored relative to the relative base, i.e. pushed on the stack
+fn pushed_constant(op: &Op) -> Option<Intcode> {
+    match op {
+        Op::Instr(instr, operands) => match (instr, &operands[..]) {
+            (Add, [Operand::Immediate(a), Operand::Immediate(b), Operand::Relative(_)]) => {
+                Some(a + b)
+            }
+            (Mul, [Operand::Immediate(a), Operand::Immediate(b), Operand::Relative(_)]) => {
+                Some(a * b)
+            }
+            _ => None,
+        },
+        Op::Data(_) => None,
+    }
+}
+
+fn operands(op: &Op) -> &[Operand] {
+    match op {
+        Op::Instr(_, operands) => operands,
+        Op::Data(_) => &[],
+    }
+}
+
+// Finds all instructions reachable from address 0 and the addresses that
+// start a basic block
+fn explore(
+    program: &[Intcode],
+) -> (
+    BTreeMap<Intcode, Op>,
+    BTreeSet<Intcode>,
+    BTreeSet<Intcode>,
+    BTreeSet<Intcode>,
+) {
+    let mut instrs = BTreeMap::new();
+    let mut leaders = BTreeSet::new();
+    let mut taken = BTreeSet::new();
+    let mut invalid = BTreeSet::new();
+    let mut work = vec![0];
+    leaders.insert(0);
+    while let Some(addr) = work.pop() {
+        if instrs.contains_key(&addr) || invalid.contains(&addr) {
+            continue;
+        }
+        let op = match decode_at(program, addr) {
+            Some(op) => op,
+            None => {
+                invalid.insert(addr);
+                continue;
+            }
+        };
+        for edge in successors(addr, &op) {
+            match edge {
+                Edge::Jump(target) => {
+                    leaders.insert(target);
+                    work.push(target);
+                }
+                Edge::Next(next) => {
+                    if is_jump(&op) {
+                        leaders.insert(next);
+                    }
+                    work.push(next);
+                }
+                Edge::Unknown => (),
+            }
+        }
+        if let Some(target) = pushed_constant(&op).filter(|&t| is_return_site(program, t)) {
+            leaders.insert(target);
+            taken.insert(target);
+            work.push(target);
+        }
+        instrs.insert(addr, op);
+    }
+    (instrs, leaders, taken, invalid)
+}
+
+fn join(addrs: impl Iterator<Item = Intcode>) -> String {
+    addrs.map(|a| a.to_string()).collect::<Vec<_>>().join(", ")
+}
+
+pub fn analyze(program: &[Intcode]) -> Analysis {
+    let (instrs, leaders, taken, invalid) = explore(program);
+    let mut blocks = BTreeMap::new();
+    for &start in leaders.iter().filter(|addr| instrs.contains_key(addr)) {
+        let mut ops = Vec::new();
+        let mut addr = start;
+        let edges = loop {
+            let op = instrs[&addr].clone();
+            let edges = successors(addr, &op);
+            let next = addr + op.num_words() as Intcode;
+            ops.push((addr, op));
+            if edges != [Edge::Next(next)] || leaders.contains(&next) || !instrs.contains_key(&next)
+            {
+                break edges;
+            }
+            addr = next;
+        };
+        blocks.insert(start, Block { ops, edges });
+    }
+    let mut code = BTreeSet::new();
+    for (&addr, op) in &instrs {
+        code.extend(addr..addr + op.num_words() as Intcode);
+    }
+    let mut writes = Vec::new();
+    for (&addr, op) in &instrs {
+        if let Op::Instr(instr, operands) = op {
+            for (i, operand) in operands.iter().enumerate() {
+                match operand {
+                    Operand::Position(target) if instr.is_store(i + 1) && code.contains(target) => {
+                        writes.push((addr, *target))
+                    }
+                    _ => (),
+                }
+            }
+        }
+    }
+    let mut regions: Vec<Region> = Vec::new();
+    for addr in (0..program.len() as Intcode).filter(|addr| !code.contains(addr)) {
+        match regions.last_mut() {
+            Some(region) if region.start + region.len as Intcode == addr => region.len += 1,
+            _ => regions.push(Region {
+                start: addr,
+                len: 1,
+                refs: 0,
+            }),
+        }
+    }
+    for region in regions.iter_mut() {
+        let range = region.start..region.start + region.len as Intcode;
+        region.refs = instrs
+            .values()
+            .filter(|op| {
+                operands(op).iter().any(|operand| match operand {
+                    Operand::Position(addr) | Operand::Immediate(addr) => range.contains(addr),
+                    Operand::Relative(_) => false,
+                })
+            })
+            .count();
+    }
+    Analysis {
+        blocks,
+        taken,
+        invalid,
+        writes,
+        regions,
+    }
+}
+
+impl Analysis {
+    pub fn blocks(&self) -> &BTreeMap<Intcode, Block> {
+        &self.blocks
+    }
+
+    // Control-flow graph in the Graphviz DOT language. Blocks with their
+    // address taken are drawn with a double border, unknown jump targets all
+    // lead to the node "?".
+    pub fn dot(&self) -> String {
+        let mut dot = String::new();
+        writeln!(dot, "digraph intcode {{").unwrap();
+        writeln!(dot, "    node [shape=box, fontname=\"monospace\"];").unwrap();
+        for (start, block) in &self.blocks {
+            let label = block
+                .ops
+                .iter()
+                .map(|(addr, op)| format!("{}: {}\\l", addr, op))
+                .collect::<String>();
+            let border = if self.taken.contains(start) {
+                ", peripheries=2"
+            } else {
+                ""
+            };
+            writeln!(dot, "    b{} [label=\"{}\"{}];", start, label, border).unwrap();
+            for edge in &block.edges {
+                match edge {
+                    Edge::Jump(target) | Edge::Next(target) => {
+                        writeln!(dot, "    b{} -> b{};", start, target).unwrap()
+                    }
+                    Edge::Unknown => {
+                        writeln!(dot, "    b{} -> unknown [style=dashed];", start).unwrap()
+                    }
+                }
+            }
+        }
+        for addr in &self.invalid {
+            writeln!(
+                dot,
+                "    b{} [label=\"{}: invalid\", color=red];",
+                addr, addr
+            )
+            .unwrap();
+        }
+        if self
+            .blocks
+            .values()
+            .any(|b| b.edges.contains(&Edge::Unknown))
+        {
+            writeln!(dot, "    unknown [label=\"?\", shape=circle];").unwrap();
+        }
+        writeln!(dot, "}}").unwrap();
+        dot
+    }
+
+    // Text report of the blocks, jumps, self-modifying code and data found
+    pub fn summary(&self) -> String {
+        let num_instrs = self.blocks.values().map(|b| b.ops.len()).sum::<usize>();
+        let data = self.regions.iter().map(|r| r.len).sum::<usize>();
+        let mut text = String::new();
+        writeln!(
+            text,
+            "Blocks: {}, instructions: {}, data: {} words",
+            self.blocks.len(),
+            num_instrs,
+            data
+        )
+        .unwrap();
+        let indirect = self
+            .blocks
+            .values()
+            .filter(|b| b.edges.contains(&Edge::Unknown))
+            .map(|b| b.ops.last().unwrap().0);
+        writeln!(text, "Indirect jumps: {}", join(indirect)).unwrap();
+        writeln!(text, "Address taken: {}", join(self.taken.iter().cloned())).unwrap();
+        writeln!(
+            text,
+            "Invalid code reached: {}",
+            join(self.invalid.iter().cloned())
+        )
+        .unwrap();
+        writeln!(text, "Self-modifying writes:").unwrap();
+        for (addr, target) in &self.writes {
+            writeln!(text, "{:>6}: writes to {}", addr, target).unwrap();
+        }
+        writeln!(text, "Data regions:").unwrap();
+        for region in &self.regions {
+            writeln!(
+                text,
+                "{:>6}: {} words, referenced by {} instructions",
+                region.start, region.len, region.refs
+            )
+            .unwrap();
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PATCH: &str = "; Calls a function doubling a value, then patches its own code
+                                 AdjustBase #stack
+                                 Add #ret, #0, rb+0
+                                 JmpIfTrue #1, #double
+                         ret:    Out [value]
+                                 Add #99, #0, [patch]
+                         patch:  Out [value]
+                                 Halt
+                         double: Mul [value], #2, [value]
+                                 JmpIfFalse #0, rb+0
+                         value:  Data 21
+                         table:  Data 1, 2, 3
+                         stack:  Data 0";
+
+    #[test]
+    fn analysis_summary() {
+        let program = assemble(PATCH).unwrap();
+        assert_eq!(
+            analyze(&program).summary(),
+            "Blocks: 3, instructions: 9, data: 5 words\n\
+             Indirect jumps: 22\n\
+             Address taken: 9\n\
+             Invalid code reached: \n\
+             Self-modifying writes:\n\
+             \x20   11: writes to 15\n\
+             Data regions:\n\
+             \x20   25: 5 words, referenced by 4 instructions\n"
+        );
+    }
+
+    #[test]
+    fn analysis_dot() {
+        let mut program = assemble(PATCH).unwrap();
+        program[17] = 4;
+        let dot = analyze(&program).dot();
+        let lines = dot.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "digraph intcode {");
+        assert!(lines.contains(&"    b0 -> b18;"));
+        assert!(lines.contains(&"    b9 -> b19;"));
+        assert!(lines.contains(&"    b18 -> unknown [style=dashed];"));
+        assert!(lines.contains(&"    b19 [label=\"19: invalid\", color=red];"));
+        let taken = lines.iter().find(|l| l.starts_with("    b9 [")).unwrap();
+        assert!(taken.ends_with("15: Out [25]\\l17: Out [1002]\\l\", peripheries=2];"));
+    }
+}
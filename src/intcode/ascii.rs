@@ -0,0 +1,145 @@
+use super::*;
+
+// Wrapper around a virtual machine running a program that reads and writes
+// ASCII text. Output values outside of the ASCII range, usually the answer to
+// a puzzle, are not part of the text and are queued separately.
+#[derive(Clone)]
+pub struct AsciiMachine {
+    vm: Vm,
+    text: String,
+    values: VecDeque<Intcode>,
+    echo: bool,
+    halted: bool,
+}
+
+fn is_ascii(val: Intcode) -> bool {
+    (0..128).contains(&val)
+}
+
+impl AsciiMachine {
+    pub fn new(vm: Vm) -> AsciiMachine {
+        AsciiMachine {
+            vm,
+            text: String::new(),
+            values: VecDeque::new(),
+            echo: false,
+            halted: false,
+        }
+    }
+
+    pub fn vm_mut(&mut self) -> &mut Vm {
+        &mut self.vm
+    }
+
+    // Prints all text sent to and received from the program when enabled
+    pub fn echo(&mut self, enable: bool) {
+        self.echo = enable;
+    }
+
+    // Queues the line, followed by a newline, as input to the program
+    pub fn send_line(&mut self, line: &str) {
+        if self.echo {
+            println!("{}", line);
+        }
+        for ch in line.chars().chain("\n".chars()) {
+            self.vm.push_input(ch as Intcode);
+        }
+    }
+
+    // Runs until done is true for the text received so far, the program
+    // halts or the program waits for input
+    fn receive(&mut self, done: impl Fn(&str) -> bool) -> Result<(), IntcodeError> {
+        while !done(&self.text) {
+            match self.vm.run()? {
+                Event::Output(val) if is_ascii(val) => {
+                    let ch = val as u8 as char;
+                    if self.echo {
+                        print!("{}", ch);
+                    }
+                    self.text.push(ch);
+                }
+                Event::Output(val) => self.values.push_back(val),
+                Event::NeedsInput => break,
+                Event::Halted => {
+                    self.halted = true;
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn take_text(&mut self, len: usize) -> String {
+        let rest = self.text.split_off(len);
+        std::mem::replace(&mut self.text, rest)
+    }
+
+    // Text up to and including the prompt. If the program stops before the
+    // prompt is output all text received is returned instead.
+    pub fn read_until(&mut self, prompt: &str) -> Result<String, IntcodeError> {
+        self.receive(|text| text.contains(prompt))?;
+        let len = match self.text.find(prompt) {
+            Some(pos) => pos + prompt.len(),
+            None => self.text.len(),
+        };
+        Ok(self.take_text(len))
+    }
+
+    // Next line of text without the newline, None if the program stops
+    // without outputting any more text
+    pub fn read_line(&mut self) -> Result<Option<String>, IntcodeError> {
+        let line = self.read_until("\n")?;
+        Ok(match line.strip_suffix('\n') {
+            Some(line) => Some(line.to_string()),
+            None if line.is_empty() => None,
+            None => Some(line),
+        })
+    }
+
+    // All text output until the program halts or waits for input
+    pub fn read_all(&mut self) -> Result<String, IntcodeError> {
+        self.receive(|_| false)?;
+        Ok(self.take_text(self.text.len()))
+    }
+
+    // True once the program has halted, any text it output may still be read
+    pub fn halted(&self) -> bool {
+        self.halted
+    }
+
+    // Oldest value output by the program that is not ASCII text
+    pub fn take_value(&mut self) -> Option<Intcode> {
+        self.values.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ECHO_LINE: &str = "; Prompt for a line of text, echo it back and output 1000
+                                   Out #78
+                                   Out #63
+                                   Out #10
+                             loop: In [ch]
+                                   Out [ch]
+                                   Equals [ch], #10, [nl]
+                                   JmpIfFalse [nl], #loop
+                                   Out #1000
+                                   Halt
+                             ch:   Data 0
+                             nl:   Data 0";
+
+    #[test]
+    fn ascii_lines() {
+        let mut machine = AsciiMachine::new(Vm::new(&assemble(ECHO_LINE).unwrap()));
+        assert_eq!(machine.read_until("?"), Ok("N?".to_string()));
+        assert_eq!(machine.read_line(), Ok(Some("".to_string())));
+        assert_eq!(machine.read_line(), Ok(None));
+        machine.send_line("Bob");
+        assert_eq!(machine.read_until("Alice"), Ok("Bob\n".to_string()));
+        assert_eq!(machine.take_value(), Some(1000));
+        assert_eq!(machine.take_value(), None);
+        assert_eq!(machine.read_all(), Ok("".to_string()));
+    }
+}
@@ -0,0 +1,316 @@
+use super::*;
+
+// Assembles programs written in the syntax produced by the disassembler.
+//
+//   ; Echo input until a zero is read
+//   loop: In [val]
+//         Out [val]
+//         JmpIfTrue [val], #loop
+//         Halt
+//   val:  Data 0
+//
+// Operands are written as "[addr]" for position mode, "#val" for immediate
+// mode and "rb+off" for relative mode. Both addresses and immediate values
+// may refer to labels, "Data" places one or more raw words in memory.
+// Instructions added with Vm::extend() are written as "Ext" followed by the
+// opcode and the operands, e.g. "Ext 42, [val], #10". The opcode must be one
+// Vm::extend() accepts.
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum AsmError {
+    UnknownMnemonic { line: usize, mnemonic: String },
+    InvalidOperand { line: usize, operand: String },
+    OperandCount { line: usize, expected: usize, found: usize },
+    ImmediateStore { line: usize },
+    UndefinedLabel { line: usize, label: String },
+    DuplicateLabel { line: usize, label: String },
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AsmError::UnknownMnemonic { line, mnemonic } => {
+                write!(f, "line {}: unknown mnemonic \"{}\"", line, mnemonic)
+            }
+            AsmError::InvalidOperand { line, operand } => {
+                write!(f, "line {}: invalid operand \"{}\"", line, operand)
+            }
+            AsmError::OperandCount {
+                line,
+                expected,
+                found,
+            } => write!(
+                f,
+                "line {}: expected {} operands, found {}",
+                line, expected, found
+            ),
+            AsmError::ImmediateStore { line } => {
+                write!(f, "line {}: store with immediate address mode", line)
+            }
+            AsmError::UndefinedLabel { line, label } => {
+                write!(f, "line {}: undefined label \"{}\"", line, label)
+            }
+            AsmError::DuplicateLabel { line, label } => {
+                write!(f, "line {}: label \"{}\" is already defined", line, label)
+            }
+        }
+    }
+}
+
+impl Error for AsmError {}
+
+enum Value {
+    Num(Intcode),
+    Label(String),
+}
+
+// Words of one source line before labels are resolved
+struct Stmt {
+    line: usize,
+    words: Vec<Value>,
+}
+
+fn is_label(name: &str) -> bool {
+    name.chars().all(|ch| ch.is_ascii_alphanumeric() || ch == '_')
+        && name
+            .chars()
+            .next()
+            .is_some_and(|ch| ch.is_ascii_alphabetic() || ch == '_')
+}
+
+fn value(line: usize, text: &str) -> Result<Value, AsmError> {
+    if let Ok(num) = text.parse::<Intcode>() {
+        Ok(Value::Num(num))
+    } else if is_label(text) {
+        Ok(Value::Label(text.to_string()))
+    } else {
+        Err(AsmError::InvalidOperand {
+            line,
+            operand: text.to_string(),
+        })
+    }
+}
+
+fn operand(line: usize, text: &str) -> Result<(AddressMode, Value), AsmError> {
+    let invalid = || AsmError::InvalidOperand {
+        line,
+        operand: text.to_string(),
+    };
+    if text.starts_with('[') && text.ends_with(']') {
+        Ok((Position, value(line, &text[1..text.len() - 1])?))
+    } else if let Some(imm) = text.strip_prefix('#') {
+        Ok((Immediate, value(line, imm)?))
+    } else if let Some(off) = text.strip_prefix("rb") {
+        let off = off.parse::<Intcode>().map_err(|_| invalid())?;
+        Ok((Relative, Value::Num(off)))
+    } else {
+        Err(invalid())
+    }
+}
+
+fn instruction(line: usize, mnemonic: &str) -> Result<Instruction, AsmError> {
+    (1..100)
+        .filter_map(to_instr)
+        .find(|instr| format!("{:?}", instr) == mnemonic)
+        .ok_or(AsmError::UnknownMnemonic {
+            line,
+            mnemonic: mnemonic.to_string(),
+        })
+}
+
+fn statement(line: usize, mnemonic: &str, args: &[&str]) -> Result<Stmt, AsmError> {
+    let mut words = Vec::new();
+    if mnemonic == "Data" {
+        for arg in args {
+            words.push(value(line, arg)?);
+        }
+    } else {
+        let (mut opcode, instr, args) = if mnemonic == "Ext" {
+            let (opcode, args) = args.split_first().ok_or(AsmError::OperandCount {
+                line,
+                expected: 1,
+                found: 0,
+            })?;
+            let opcode = opcode
+                .parse::<Intcode>()
+                .ok()
+                .filter(|&opcode| extension::is_free(opcode))
+                .ok_or_else(|| AsmError::InvalidOperand {
+                    line,
+                    operand: opcode.to_string(),
+                })?;
+            (opcode, None, args)
+        } else {
+            let instr = instruction(line, mnemonic)?;
+            if args.len() != instr.num_params() {
+                return Err(AsmError::OperandCount {
+                    line,
+                    expected: instr.num_params(),
+                    found: args.len(),
+                });
+            }
+            (instr as Intcode, Some(instr), args)
+        };
+        let mut scale = 100;
+        for (i, arg) in args.iter().enumerate() {
+            let (mode, val) = operand(line, arg)?;
+            if mode == Immediate && instr.is_some_and(|instr| instr.is_store(i + 1)) {
+                return Err(AsmError::ImmediateStore { line });
+            }
+            opcode += mode as Intcode * scale;
+            scale *= 10;
+            words.push(val);
+        }
+        words.insert(0, Value::Num(opcode));
+    }
+    Ok(Stmt { line, words })
+}
+
+// Translates assembly source to an Intcode program
+pub fn assemble(source: &str) -> Result<Vec<Intcode>, AsmError> {
+    let mut labels = HashMap::new();
+    let mut stmts = Vec::new();
+    let mut addr = 0;
+    for (idx, text) in source.lines().enumerate() {
+        let line = idx + 1;
+        let mut text = text.split(';').next().unwrap().trim();
+        if let Some(colon) = text.find(':') {
+            let label = text[..colon].trim();
+            if !is_label(label) {
+                return Err(AsmError::InvalidOperand {
+                    line,
+                    operand: label.to_string(),
+                });
+            }
+            if labels.insert(label.to_string(), addr).is_some() {
+                return Err(AsmError::DuplicateLabel {
+                    line,
+                    label: label.to_string(),
+                });
+            }
+            text = text[colon + 1..].trim();
+        }
+        if text.is_empty() {
+            continue;
+        }
+        let (mnemonic, rest) = text.split_at(text.find(' ').unwrap_or(text.len()));
+        let args = rest
+            .split(',')
+            .map(|arg| arg.trim())
+            .filter(|arg| !arg.is_empty())
+            .collect::<Vec<_>>();
+        let stmt = statement(line, mnemonic, &args)?;
+        addr += stmt.words.len() as Intcode;
+        stmts.push(stmt);
+    }
+    let mut program = Vec::new();
+    for stmt in stmts {
+        for word in stmt.words {
+            program.push(match word {
+                Value::Num(num) => num,
+                Value::Label(label) => {
+                    *labels.get(&label).ok_or(AsmError::UndefinedLabel {
+                        line: stmt.line,
+                        label,
+                    })?
+                }
+            });
+        }
+    }
+    Ok(program)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ECHO: &str = "; Echo input until a zero is read
+                        loop: In [val]
+                              Out [val]
+                              JmpIfTrue [val], #loop
+                              Halt
+                        val:  Data 0";
+
+    #[test]
+    fn asm_echo() {
+        let program = assemble(ECHO).unwrap();
+        assert_eq!(program, vec![3, 8, 4, 8, 1005, 8, 0, 99, 0]);
+        let mut vm = Vm::new(&program);
+        for &val in &[3, 1, 0] {
+            vm.push_input(val);
+        }
+        assert_eq!(vm.output(), Ok(Some(3)));
+        assert_eq!(vm.output(), Ok(Some(1)));
+        assert_eq!(vm.output(), Ok(Some(0)));
+        assert_eq!(vm.run(), Ok(Event::Halted));
+    }
+
+    #[test]
+    fn asm_relative() {
+        let program = assemble(
+            "AdjustBase #buf
+             Mul #11, #22, rb+1
+             Out [out]
+             Halt
+             buf: Data 0
+             out: Data 0",
+        )
+        .unwrap();
+        assert_eq!(program, vec![109, 9, 21102, 11, 22, 1, 4, 10, 99, 0, 0]);
+        assert_eq!(Vm::new(&program).output(), Ok(Some(242)));
+    }
+
+    #[test]
+    fn asm_roundtrip() {
+        let program = vec![
+            3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31, 1106, 0, 36, 98, 0,
+            0, 1002, 21, 125, 20, 4, 20, 1105, 1, 46, 104, 999, 1105, 1, 46, 1101, 1000, 1, 20, 4,
+            20, 1105, 1, 46, 98, 99, 109, -7, 204, 3, 21101, 1, 2, -4,
+        ];
+        let source = disassemble(&program)
+            .iter()
+            .map(|line| line.op.to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        assert_eq!(assemble(&source), Ok(program));
+    }
+
+    #[test]
+    fn asm_errors() {
+        assert_eq!(
+            assemble("Add #1, #2"),
+            Err(AsmError::OperandCount {
+                line: 1,
+                expected: 3,
+                found: 2
+            })
+        );
+        assert_eq!(
+            assemble("Halt\nIn #3"),
+            Err(AsmError::ImmediateStore { line: 2 })
+        );
+        assert_eq!(
+            assemble("Out [nowhere]"),
+            Err(AsmError::UndefinedLabel {
+                line: 1,
+                label: "nowhere".to_string()
+            })
+        );
+        assert_eq!(
+            assemble("Jmp #0"),
+            Err(AsmError::UnknownMnemonic {
+                line: 1,
+                mnemonic: "Jmp".to_string()
+            })
+        );
+        for opcode in &["142", "1", "0", "x"] {
+            assert_eq!(
+                assemble(&format!("Ext {}, #1", opcode)),
+                Err(AsmError::InvalidOperand {
+                    line: 1,
+                    operand: opcode.to_string()
+                })
+            );
+        }
+    }
+}
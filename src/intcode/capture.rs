@@ -0,0 +1,160 @@
+use super::*;
+
+// Entry in the log of a network, time is the number of the round in which the
+// packet was sent or the NAT made its decision. Written as one line of text:
+//
+//   0 boot 50
+//   3 packet 12 255 4417 28813
+//   3 nat continue
+//   5 idle
+//   5 nat send 255 0 4417 28813
+//   9 nat stop 28813
+#[derive(Clone, Debug, PartialEq)]
+pub enum Record {
+    Boot { time: u64, size: usize },
+    Packet { time: u64, packet: Packet },
+    Idle { time: u64 },
+    Nat { time: u64, verdict: Verdict },
+}
+
+pub type SharedCapture = Arc<Mutex<Vec<Record>>>;
+
+static DEFAULT_CAPTURE: Mutex<Option<SharedCapture>> = Mutex::new(None);
+
+// Logs the traffic of every network created from now on, or stops doing so if
+// capture is None
+pub fn capture_all(capture: Option<SharedCapture>) {
+    *DEFAULT_CAPTURE.lock().unwrap() = capture;
+}
+
+pub(super) fn default_capture() -> Option<SharedCapture> {
+    DEFAULT_CAPTURE.lock().unwrap().clone()
+}
+
+// Result of replaying a log, a divergence is reported with the packet the log
+// expected and the packet actually sent, either may be missing
+#[derive(Debug, PartialEq)]
+pub enum Replay {
+    Reproduced,
+    Diverged {
+        time: u64,
+        expected: Option<Packet>,
+        sent: Option<Packet>,
+    },
+}
+
+impl Record {
+    pub fn time(&self) -> u64 {
+        match *self {
+            Record::Boot { time, .. } => time,
+            Record::Packet { time, .. } => time,
+            Record::Idle { time } => time,
+            Record::Nat { time, .. } => time,
+        }
+    }
+
+    // Reads a record in the format written by Display
+    pub fn parse(line: &str) -> Option<Record> {
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        let num = |idx: usize| words.get(idx)?.parse::<Intcode>().ok();
+        let packet = |idx: usize| {
+            Some(Packet {
+                src: num(idx)?,
+                dst: num(idx + 1)?,
+                x: num(idx + 2)?,
+                y: num(idx + 3)?,
+            })
+        };
+        let time = words.first()?.parse::<u64>().ok()?;
+        Some(match &words[1..] {
+            ["boot", size] => Record::Boot {
+                time,
+                size: size.parse::<usize>().ok()?,
+            },
+            ["packet", _, _, _, _] => Record::Packet {
+                time,
+                packet: packet(2)?,
+            },
+            ["idle"] => Record::Idle { time },
+            ["nat", "continue"] => Record::Nat {
+                time,
+                verdict: Verdict::Continue,
+            },
+            ["nat", "send", _, _, _, _] => Record::Nat {
+                time,
+                verdict: Verdict::Send(packet(3)?),
+            },
+            ["nat", "stop", _] => Record::Nat {
+                time,
+                verdict: Verdict::Stop(num(3)?),
+            },
+            _ => return None,
+        })
+    }
+}
+
+impl fmt::Display for Packet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {} {}", self.src, self.dst, self.x, self.y)
+    }
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ", self.time())?;
+        match self {
+            Record::Boot { size, .. } => write!(f, "boot {}", size),
+            Record::Packet { packet, .. } => write!(f, "packet {}", packet),
+            Record::Idle { .. } => write!(f, "idle"),
+            Record::Nat { verdict, .. } => match verdict {
+                Verdict::Continue => write!(f, "nat continue"),
+                Verdict::Send(packet) => write!(f, "nat send {}", packet),
+                Verdict::Stop(result) => write!(f, "nat stop {}", result),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capture_format() {
+        let log = "0 boot 50\n\
+                   3 packet 12 255 4417 -28813\n\
+                   3 nat continue\n\
+                   5 idle\n\
+                   5 nat send 255 0 4417 28813\n\
+                   9 nat stop 28813";
+        let records = log
+            .lines()
+            .map(Record::parse)
+            .collect::<Option<Vec<_>>>()
+            .unwrap();
+        assert_eq!(
+            records[1],
+            Record::Packet {
+                time: 3,
+                packet: Packet {
+                    src: 12,
+                    dst: 255,
+                    x: 4417,
+                    y: -28813
+                }
+            }
+        );
+        let text = records.iter().map(|r| r.to_string()).collect::<Vec<_>>();
+        assert_eq!(text.join("\n"), log);
+        for bad in &[
+            "",
+            "x idle",
+            "1 idle 2",
+            "1 packet 1 2 3",
+            "1 nat go",
+            "1 boot -1",
+        ] {
+            assert_eq!(Record::parse(bad), None);
+        }
+    }
+}
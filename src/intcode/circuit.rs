@@ -0,0 +1,303 @@
+use super::*;
+
+struct Node {
+    boot: Vec<Intcode>,
+    targets: Vec<usize>,
+    is_output: bool,
+}
+
+// Machines running the same program, each on its own thread, where every value
+// output by a machine is sent to all machines it is connected to. Machines
+// are identified by the order in which they were added. Chains, rings, fan-out
+// and fan-in are all built with connect, when several machines are connected
+// to the same machine their values arrive in no particular order.
+pub struct Circuit {
+    program: Vec<Intcode>,
+    nodes: Vec<Node>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CircuitError {
+    // Machine that stopped for any other reason than halting
+    Failed { node: usize, exit: Exit },
+    // Every machine still running waits for input no machine will send
+    Deadlocked { waiting: Vec<usize> },
+}
+
+// What the machines of a running circuit tell the caller of run
+enum Report {
+    Value(Intcode),
+    Stopped(usize),
+    Deadlocked(Vec<usize>),
+}
+
+// Progress of the machines of a running circuit, shared by all of them
+struct Progress {
+    // Machines sending values to each machine
+    sources: Vec<Vec<usize>>,
+    // Values sent to each machine and not read yet
+    queued: Vec<usize>,
+    waiting: Vec<bool>,
+    stopped: Vec<bool>,
+}
+
+impl Progress {
+    // Machines that are all waiting for input when none of them has any queued
+    // and none can be woken by its sources stopping, otherwise None
+    fn deadlocked(&self) -> Option<Vec<usize>> {
+        let running = (0..self.queued.len())
+            .filter(|&node| !self.stopped[node])
+            .collect::<Vec<_>>();
+        let stuck = |&node: &usize| {
+            self.waiting[node]
+                && self.queued[node] == 0
+                && self.sources[node].iter().any(|&src| !self.stopped[src])
+        };
+        Some(running).filter(|running| !running.is_empty() && running.iter().all(stuck))
+    }
+}
+
+// Output of a machine in a circuit, sends every value to all machines still
+// listening. Values for the caller of run are passed on, and the caller is
+// told when the machine has stopped.
+struct Tee {
+    node: usize,
+    targets: Vec<(usize, Sender<Intcode>)>,
+    sink: Option<Sender<Report>>,
+    report: Sender<Report>,
+    progress: Arc<Mutex<Progress>>,
+}
+
+impl Output for Tee {
+    fn write(&mut self, val: Intcode) -> bool {
+        let mut progress = self.progress.lock().unwrap();
+        self.targets.retain(|&(to, ref tx)| {
+            let sent = tx.send(val).is_ok();
+            if sent {
+                progress.queued[to] += 1;
+            }
+            sent
+        });
+        if let Some(sink) = &self.sink {
+            sink.send(Report::Value(val)).unwrap();
+        }
+        !self.targets.is_empty() || self.sink.is_some()
+    }
+}
+
+impl Drop for Tee {
+    fn drop(&mut self) {
+        let mut progress = self.progress.lock().unwrap();
+        progress.stopped[self.node] = true;
+        progress.waiting[self.node] = false;
+        progress.queued[self.node] = 0;
+        self.report
+            .send(Report::Stopped(self.node))
+            .unwrap_or_default();
+        if let Some(waiting) = progress.deadlocked() {
+            self.report
+                .send(Report::Deadlocked(waiting))
+                .unwrap_or_default();
+        }
+    }
+}
+
+impl Circuit {
+    pub fn new(program: &[Intcode]) -> Circuit {
+        Circuit {
+            program: program.to_vec(),
+            nodes: Vec::new(),
+        }
+    }
+
+    // Adds a machine that is given the boot values before any other input
+    pub fn add(&mut self, boot: &[Intcode]) -> usize {
+        self.nodes.push(Node {
+            boot: boot.to_vec(),
+            targets: Vec::new(),
+            is_output: false,
+        });
+        self.nodes.len() - 1
+    }
+
+    pub fn connect(&mut self, from: usize, to: usize) {
+        self.nodes[from].targets.push(to);
+    }
+
+    // Connects each machine to the next one
+    pub fn chain(&mut self, nodes: &[usize]) {
+        for pair in nodes.windows(2) {
+            self.connect(pair[0], pair[1]);
+        }
+    }
+
+    // Connects each machine to the next one and the last one to the first
+    pub fn ring(&mut self, nodes: &[usize]) {
+        self.chain(nodes);
+        if let (Some(&last), Some(&first)) = (nodes.last(), nodes.first()) {
+            self.connect(last, first);
+        }
+    }
+
+    // Values output by the machine are also returned by run
+    pub fn output(&mut self, node: usize) {
+        self.nodes[node].is_output = true;
+    }
+
+    // Runs all machines until they have stopped and returns the values output
+    // by the output machines. If a machine fails, or all machines still running
+    // wait for each other, all machines are cancelled.
+    pub fn run(&self) -> Result<Vec<Intcode>, CircuitError> {
+        let (report, events) = channel();
+        let (txs, rxs): (Vec<_>, Vec<_>) = self.nodes.iter().map(|_| channel()).unzip();
+        for (node, tx) in self.nodes.iter().zip(&txs) {
+            for &val in &node.boot {
+                tx.send(val).unwrap();
+            }
+        }
+        let mut sources = vec![Vec::new(); self.nodes.len()];
+        for (from, node) in self.nodes.iter().enumerate() {
+            for &to in &node.targets {
+                sources[to].push(from);
+            }
+        }
+        let progress = Arc::new(Mutex::new(Progress {
+            sources,
+            queued: self.nodes.iter().map(|node| node.boot.len()).collect(),
+            waiting: vec![false; self.nodes.len()],
+            stopped: vec![false; self.nodes.len()],
+        }));
+        let mut threads = self
+            .nodes
+            .iter()
+            .zip(rxs)
+            .enumerate()
+            .map(|(id, (node, rx))| {
+                let tee = Tee {
+                    node: id,
+                    targets: node
+                        .targets
+                        .iter()
+                        .map(|&to| (to, txs[to].clone()))
+                        .collect(),
+                    sink: Some(report.clone()).filter(|_| node.is_output),
+                    report: report.clone(),
+                    progress: progress.clone(),
+                };
+                let (progress, report) = (progress.clone(), report.clone());
+                let watch = move |waiting| {
+                    let mut progress = progress.lock().unwrap();
+                    progress.waiting[id] = waiting;
+                    if !waiting {
+                        progress.queued[id] -= 1;
+                    } else if let Some(waiting) = progress.deadlocked() {
+                        report.send(Report::Deadlocked(waiting)).unwrap_or_default();
+                    }
+                };
+                Some(spawn_watched(Vm::new(&self.program), rx, tee, watch))
+            })
+            .collect::<Vec<_>>();
+        drop((report, txs));
+        let mut values = Vec::new();
+        let cancel = |threads: Vec<Option<VmThread>>| {
+            for thread in threads.into_iter().flatten() {
+                thread.cancel();
+                thread.join();
+            }
+        };
+        for event in events {
+            match event {
+                Report::Value(val) => values.push(val),
+                Report::Stopped(node) => {
+                    let exit = threads[node].take().unwrap().join();
+                    if exit != Exit::Halted {
+                        cancel(threads);
+                        return Err(CircuitError::Failed { node, exit });
+                    }
+                }
+                Report::Deadlocked(waiting) => {
+                    cancel(threads);
+                    return Err(CircuitError::Deadlocked { waiting });
+                }
+            }
+        }
+        Ok(values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SUM: &str = "; Outputs the sum of two values
+                            In [a]
+                            In [b]
+                            Add [a], [b], [a]
+                            Out [a]
+                            Halt
+                       a:   Data 0
+                       b:   Data 0";
+
+    const JUMP: &str = "; Jumps to the address read until it faults
+                       loop: In [addr]
+                             JmpIfTrue #1, [addr]
+                       addr: Data 0";
+
+    #[test]
+    fn circuit_fan_out_in() {
+        let mut circuit = Circuit::new(&assemble(SUM).unwrap());
+        let source = circuit.add(&[2, 3]);
+        let left = circuit.add(&[10]);
+        let right = circuit.add(&[100]);
+        let sum = circuit.add(&[]);
+        for &branch in &[left, right] {
+            circuit.chain(&[source, branch, sum]);
+        }
+        circuit.output(source);
+        circuit.output(sum);
+        assert_eq!(circuit.run(), Ok(vec![5, 120]));
+    }
+
+    #[test]
+    fn circuit_cancel() {
+        let mut circuit = Circuit::new(&assemble(JUMP).unwrap());
+        let waiting = circuit.add(&[0]);
+        let faulty = circuit.add(&[999]);
+        circuit.ring(&[waiting]);
+        circuit.connect(waiting, faulty);
+        assert!(matches!(
+            circuit.run(),
+            Err(CircuitError::Failed {
+                node,
+                exit: Exit::Fault(IntcodeError::InvalidInstruction { ip: 999, .. }),
+            }) if node == faulty
+        ));
+    }
+
+    #[test]
+    fn circuit_deadlock() {
+        let mut circuit = Circuit::new(&assemble(SUM).unwrap());
+        let left = circuit.add(&[1]);
+        let right = circuit.add(&[2]);
+        circuit.ring(&[left, right]);
+        circuit.output(left);
+        assert_eq!(
+            circuit.run(),
+            Err(CircuitError::Deadlocked {
+                waiting: vec![left, right]
+            })
+        );
+        let mut circuit = Circuit::new(&assemble(SUM).unwrap());
+        let source = circuit.add(&[2, 3]);
+        let left = circuit.add(&[]);
+        let right = circuit.add(&[]);
+        circuit.chain(&[source, left]);
+        circuit.ring(&[left, right]);
+        assert_eq!(
+            circuit.run(),
+            Err(CircuitError::Deadlocked {
+                waiting: vec![left, right]
+            })
+        );
+    }
+}
@@ -0,0 +1,376 @@
+use super::disasm::{decode, Op};
+use super::*;
+use std::collections::{BTreeMap, BTreeSet};
+
+const HELP: &str = "\
+step [N]          execute N instructions, default is one
+back [N]          go back N instructions, default is one
+goto N            go to where N instructions have been executed since start
+continue          run until a breakpoint, a watchpoint, input is needed, halt
+                  or 100000000 instructions have been executed
+break ADDR        stop before the instruction at ADDR is executed
+delete ADDR       remove the breakpoint at ADDR
+watch ADDR        stop when the value stored at ADDR changes
+unwatch ADDR      remove the watchpoint at ADDR
+lastwrite ADDR    find the last instruction that stored a value at ADDR
+input VAL...      queue values to be read by In instructions
+ascii TEXT        queue TEXT followed by a newline as ASCII values
+regs              show instruction pointer, relative base and queued input
+mem ADDR [LEN]    show LEN words of memory starting at ADDR, default is 8
+list [ADDR] [N]   disassemble N instructions from ADDR, default is 5 from ip
+quit              leave the debugger";
+
+// Stops continue in programs that never halt
+const CONTINUE_BUDGET: u64 = 100_000_000;
+
+// Most instructions listed or words of memory shown by one command
+const MAX_COUNT: usize = 1000;
+
+// Instructions between snapshots of the recorded run, going back replays at
+// most this many instructions
+const SNAPSHOT_INTERVAL: u64 = 100_000;
+
+// Interactive debugger around a virtual machine. Commands are given as text,
+// one at a time, and the result of each command is returned as text. The run
+// is recorded, so the debugger can also go back in time.
+pub struct Debugger {
+    vm: Vm,
+    breakpoints: BTreeSet<Intcode>,
+    watchpoints: BTreeMap<Intcode, Intcode>,
+}
+
+fn parse_addr(arg: Option<&&str>) -> Result<Intcode, String> {
+    let arg = arg.ok_or("Missing address")?;
+    arg.parse::<Intcode>()
+        .map_err(|_| format!("Invalid address \"{}\"", arg))
+}
+
+impl Debugger {
+    pub fn new(program: &[Intcode]) -> Debugger {
+        let mut vm = Vm::new(program);
+        vm.record(Some(SNAPSHOT_INTERVAL));
+        Debugger {
+            vm,
+            breakpoints: BTreeSet::new(),
+            watchpoints: BTreeMap::new(),
+        }
+    }
+
+    // Decodes the instruction at addr from the memory of the machine, words
+    // past the highest address read as zero
+    fn decode_at(&self, addr: Intcode) -> Op {
+        let words = (0..4)
+            .map(|i| addr.checked_add(i).map_or(0, |a| self.vm.peek(a)))
+            .collect::<Vec<_>>();
+        decode(&words, 0).unwrap_or(Op::Data(words[0]))
+    }
+
+    fn list(&self, addr: Intcode, n: usize) -> Vec<String> {
+        let mut lines = Vec::new();
+        let mut addr = addr;
+        for _ in 0..n {
+            let op = self.decode_at(addr);
+            let marker = if addr == self.vm.ip() { "=>" } else { "  " };
+            lines.push(format!("{}{:>6}: {}", marker, addr, op));
+            addr = match addr.checked_add(op.num_words() as Intcode) {
+                Some(next) => next,
+                None => break,
+            };
+        }
+        lines
+    }
+
+    // Executes up to max_steps instructions, or until something interesting
+    // happens if there is no limit. With trace every instruction is listed,
+    // otherwise only the next one.
+    fn resume(&mut self, max_steps: Option<usize>, trace: bool) -> Vec<String> {
+        let mut lines = Vec::new();
+        let mut steps = 0;
+        while max_steps.is_none_or(|max| steps < max) {
+            if trace {
+                lines.extend(self.list(self.vm.ip(), 1));
+            }
+            match self.vm.step() {
+                Err(err) => {
+                    lines.push(format!("Fault: {}", err));
+                    return lines;
+                }
+                Ok(Some(Event::Output(val))) => lines.push(format!("Output: {}", val)),
+                Ok(Some(Event::NeedsInput)) => {
+                    lines.push("Waiting for input".to_string());
+                    return lines;
+                }
+                Ok(Some(Event::Halted)) => {
+                    lines.push("Halted".to_string());
+                    return lines;
+                }
+                Ok(None) => (),
+            }
+            steps += 1;
+            let mut stop = false;
+            for (&addr, last) in self.watchpoints.iter_mut() {
+                let val = self.vm.peek(addr);
+                if val != *last {
+                    lines.push(format!("Watchpoint {}: {} -> {}", addr, last, val));
+                    *last = val;
+                    stop = true;
+                }
+            }
+            if self.breakpoints.contains(&self.vm.ip()) {
+                lines.push(format!("Breakpoint {}", self.vm.ip()));
+                stop = true;
+            }
+            if stop {
+                break;
+            }
+        }
+        if !trace {
+            lines.extend(self.list(self.vm.ip(), 1));
+        }
+        lines
+    }
+
+    // Goes back to where step instructions had been executed
+    fn rewind(&mut self, step: u64) -> Vec<String> {
+        if !self.vm.rewind(step) {
+            return vec![format!("Step {} was not recorded", step)];
+        }
+        for (&addr, last) in self.watchpoints.iter_mut() {
+            *last = self.vm.peek(addr);
+        }
+        let mut lines = vec![format!("Step {}", step)];
+        lines.extend(self.list(self.vm.ip(), 1));
+        lines
+    }
+
+    fn last_write(&self, addr: Intcode) -> Vec<String> {
+        match self.vm.last_write(addr) {
+            Some((step, ip)) => vec![format!("Written at step {} by instruction {}", step, ip)],
+            None => vec![format!("{} was never written to", addr)],
+        }
+    }
+
+    fn regs(&self) -> Vec<String> {
+        vec![
+            format!("ip  : {}", self.vm.ip()),
+            format!("rb  : {}", self.vm.relative_base()),
+            format!("input queued : {}", self.vm.pending_input()),
+        ]
+    }
+
+    // Words are counted from addr, so addr + len must not overflow
+    fn mem(&self, addr: Intcode, len: Intcode) -> Vec<String> {
+        (0..len)
+            .step_by(8)
+            .map(|row| {
+                let words = (row..(row + 8).min(len))
+                    .map(|offset| format!("{:>8}", self.vm.peek(addr + offset)))
+                    .collect::<String>();
+                format!("{:>6}:{}", addr + row, words)
+            })
+            .collect()
+    }
+
+    fn exec(&mut self, cmd: &str, args: &[&str]) -> Result<Vec<String>, String> {
+        let num = |idx: usize, default: Intcode| {
+            args.get(idx)
+                .map_or(Ok(default), |arg| arg.parse::<Intcode>())
+                .map_err(|_| format!("Invalid number \"{}\"", args[idx]))
+        };
+        // Number of steps, instructions or words, which can't be negative
+        let count = |idx: usize, default: usize| {
+            args.get(idx)
+                .map_or(Ok(default), |arg| arg.parse::<usize>())
+                .map_err(|_| format!("Invalid count \"{}\"", args[idx]))
+        };
+        // Count of instructions or words to show from addr, an instruction
+        // takes at most four words
+        let shown = |idx: usize, default: usize, addr: Intcode, words: usize| {
+            let n = count(idx, default)?;
+            if n > MAX_COUNT {
+                return Err(format!("Count {} is more than {}", n, MAX_COUNT));
+            }
+            match addr.checked_add((n * words) as Intcode) {
+                Some(_) => Ok(n),
+                None => Err(format!("Address {} is too high", addr)),
+            }
+        };
+        Ok(match cmd {
+            "s" | "step" => self.resume(Some(count(0, 1)?), true),
+            "back" => {
+                let steps = count(0, 1)? as u64;
+                self.rewind(self.vm.executed().saturating_sub(steps))
+            }
+            "goto" => {
+                let arg = args.first().ok_or("Missing step")?;
+                let step = arg
+                    .parse::<u64>()
+                    .map_err(|_| format!("Invalid step \"{}\"", arg))?;
+                match step.checked_sub(self.vm.executed()) {
+                    Some(ahead) if ahead > 0 => self.resume(Some(ahead as usize), false),
+                    _ => self.rewind(step),
+                }
+            }
+            "c" | "continue" => {
+                self.vm.limit_instructions(Some(CONTINUE_BUDGET));
+                let lines = self.resume(None, false);
+                self.vm.limit_instructions(None);
+                lines
+            }
+            "b" | "break" => {
+                self.breakpoints.insert(parse_addr(args.first())?);
+                Vec::new()
+            }
+            "d" | "delete" => {
+                self.breakpoints.remove(&parse_addr(args.first())?);
+                Vec::new()
+            }
+            "w" | "watch" => {
+                let addr = parse_addr(args.first())?;
+                self.watchpoints.insert(addr, self.vm.peek(addr));
+                Vec::new()
+            }
+            "unwatch" => {
+                self.watchpoints.remove(&parse_addr(args.first())?);
+                Vec::new()
+            }
+            "lastwrite" => self.last_write(parse_addr(args.first())?),
+            "i" | "input" => {
+                for idx in 0..args.len() {
+                    self.vm.push_input(num(idx, 0)?);
+                }
+                Vec::new()
+            }
+            "a" | "ascii" => {
+                for ch in args.join(" ").chars().chain("\n".chars()) {
+                    self.vm.push_input(ch as Intcode);
+                }
+                Vec::new()
+            }
+            "r" | "regs" => self.regs(),
+            "x" | "mem" => {
+                let addr = parse_addr(args.first())?;
+                self.mem(addr, shown(1, 8, addr, 1)? as Intcode)
+            }
+            "l" | "list" => {
+                let addr = num(0, self.vm.ip())?;
+                self.list(addr, shown(1, 5, addr, 4)?)
+            }
+            "h" | "help" => HELP.lines().map(|l| l.to_string()).collect(),
+            _ => return Err(format!("Unknown command \"{}\", try \"help\"", cmd)),
+        })
+    }
+
+    // Executes one command line, None is returned when the user wants to quit
+    pub fn command(&mut self, line: &str) -> Option<String> {
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        match words.first() {
+            None => Some(String::new()),
+            Some(&"q") | Some(&"quit") => None,
+            Some(cmd) => Some(match self.exec(cmd, &words[1..]) {
+                Ok(lines) => lines.join("\n"),
+                Err(err) => err,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Adds input to a sum at address 16 until a zero is read, then outputs it
+    const SUM: [Intcode; 17] = [
+        3, 15, 1006, 15, 12, 1, 15, 16, 16, 1105, 1, 0, 4, 16, 99, 0, 0,
+    ];
+
+    fn run_cmds(dbg: &mut Debugger, cmds: &[&str]) -> Vec<String> {
+        cmds.iter().map(|cmd| dbg.command(cmd).unwrap()).collect()
+    }
+
+    #[test]
+    fn dbg_step() {
+        let mut dbg = Debugger::new(&SUM);
+        assert_eq!(
+            run_cmds(&mut dbg, &["step", "input 7", "step 2", "regs"]),
+            vec![
+                "=>     0: In [15]\nWaiting for input",
+                "",
+                "=>     0: In [15]\n=>     2: JmpIfFalse [15], #12",
+                "ip  : 5\nrb  : 0\ninput queued : 0",
+            ]
+        );
+        assert_eq!(dbg.command("step -1").unwrap(), "Invalid count \"-1\"");
+        assert_eq!(dbg.command("list 0 -1").unwrap(), "Invalid count \"-1\"");
+        assert_eq!(dbg.command("back -5").unwrap(), "Invalid count \"-5\"");
+        assert_eq!(
+            dbg.command("list 0 18446744073709551615").unwrap(),
+            "Count 18446744073709551615 is more than 1000"
+        );
+        assert_eq!(
+            dbg.command("mem 9223372036854775807 4").unwrap(),
+            "Address 9223372036854775807 is too high"
+        );
+        assert_eq!(
+            dbg.command("list 9223372036854775806 2").unwrap(),
+            "Address 9223372036854775806 is too high"
+        );
+        assert_eq!(
+            dbg.command("mem 9223372036854775800 7").unwrap(),
+            "9223372036854775800:       0       0       0       0       0       0       0"
+        );
+    }
+
+    #[test]
+    fn dbg_breakpoint() {
+        let mut dbg = Debugger::new(&SUM);
+        run_cmds(&mut dbg, &["input 1 2 0", "break 5"]);
+        assert_eq!(
+            dbg.command("c").unwrap(),
+            "Breakpoint 5\n=>     5: Add [15], [16], [16]"
+        );
+        assert_eq!(
+            dbg.command("c").unwrap(),
+            "Breakpoint 5\n=>     5: Add [15], [16], [16]"
+        );
+        assert_eq!(dbg.command("mem 15 2").unwrap(), "    15:       2       1");
+        run_cmds(&mut dbg, &["delete 5"]);
+        assert_eq!(dbg.command("c").unwrap(), "Output: 3\nHalted");
+    }
+
+    #[test]
+    fn dbg_watchpoint() {
+        let mut dbg = Debugger::new(&SUM);
+        run_cmds(&mut dbg, &["input 4 0", "watch 16"]);
+        assert_eq!(
+            dbg.command("continue").unwrap(),
+            "Watchpoint 16: 0 -> 4\n=>     9: JmpIfTrue #1, #0"
+        );
+        assert_eq!(dbg.command("continue").unwrap(), "Output: 4\nHalted");
+        assert_eq!(dbg.command("quit"), None);
+    }
+
+    #[test]
+    fn dbg_reverse() {
+        let mut dbg = Debugger::new(&SUM);
+        run_cmds(&mut dbg, &["input 4 5 0"]);
+        assert_eq!(dbg.command("c").unwrap(), "Output: 9\nHalted");
+        assert_eq!(
+            dbg.command("lastwrite 16").unwrap(),
+            "Written at step 6 by instruction 5"
+        );
+        assert_eq!(
+            dbg.command("goto 6").unwrap(),
+            "Step 6\n=>     5: Add [15], [16], [16]"
+        );
+        assert_eq!(dbg.command("mem 15 2").unwrap(), "    15:       5       4");
+        assert_eq!(dbg.command("back 2").unwrap(), "Step 4\n=>     0: In [15]");
+        run_cmds(&mut dbg, &["watch 16"]);
+        assert_eq!(
+            dbg.command("goto 7").unwrap(),
+            "Watchpoint 16: 4 -> 9\n=>     9: JmpIfTrue #1, #0"
+        );
+        assert_eq!(dbg.command("goto 99").unwrap(), "Output: 9\nHalted");
+        assert_eq!(dbg.command("goto x").unwrap(), "Invalid step \"x\"");
+    }
+}
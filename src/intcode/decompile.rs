@@ -0,0 +1,636 @@
+use super::analysis::{Analysis, Block};
+use super::disasm::{Op, Operand};
+use super::*;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+// Turns the basic blocks found by the analysis into pseudo-code. Programs
+// compiled to Intcode call functions by pushing the arguments and the return
+// address relative to the relative base and jumping to the function, which
+// starts by moving the relative base past its frame with AdjustBase and
+// returns by moving it back and jumping to the return address:
+//
+//   Add #5, #0, rb+1            arg1 = 5
+//   Add #9, #0, rb+0            call f20
+//   JmpIfTrue #1, #20
+//   ...
+//   20: AdjustBase #3           fn f20(frame 3) {
+//   ...                             ...
+//   AdjustBase #-3                  return
+//   JmpIfTrue #1, rb+0          }
+//
+// Frame slots are named v1, v2... with the first parameter in v1, values
+// passed to and returned from functions called are named arg1, arg2...
+// Branches are turned into loops and if/else where the jumps nest properly,
+// other jumps are left as goto.
+
+// How control leaves a basic block once the idioms have been recognized
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Exit {
+    Next(Intcode),
+    Goto(Intcode),
+    Branch {
+        target: Intcode,
+        next: Intcode,
+    },
+    Call {
+        target: Option<Intcode>,
+        next: Intcode,
+    },
+    Return,
+    Stop,
+}
+
+struct Function {
+    entry: Intcode,
+    frame: Intcode,
+    blocks: Vec<Intcode>,
+}
+
+enum Line {
+    Label(Intcode),
+    Text(usize, String),
+}
+
+fn is_jump(instr: Instruction) -> bool {
+    instr == JmpIfTrue || instr == JmpIfFalse
+}
+
+// Value written by an instruction that stores a constant, e.g. Add #9, #0
+fn constant(op: &Op) -> Option<(Intcode, Operand)> {
+    match op {
+        Op::Instr(Add, operands) => match operands[..] {
+            [Operand::Immediate(a), Operand::Immediate(b), dst] => Some((a + b, dst)),
+            _ => None,
+        },
+        Op::Instr(Mul, operands) => match operands[..] {
+            [Operand::Immediate(a), Operand::Immediate(b), dst] => Some((a * b, dst)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// Recognizes calls and returns at the end of the block, returns the exit and
+// the instructions that are part of the idiom and not shown
+fn exit(block: &Block) -> (Exit, Vec<Intcode>) {
+    let end = block.end();
+    let (addr, op) = block.ops.last().unwrap();
+    let (instr, operands) = match op {
+        Op::Instr(instr, operands) if is_jump(*instr) => (*instr, operands),
+        Op::Instr(Halt, _) => return (Exit::Stop, vec![]),
+        _ => return (Exit::Next(end), vec![]),
+    };
+    // The target may be patched by an earlier instruction, like any operand
+    let patched = block.ops.iter().any(|(_, op)| match op {
+        Op::Instr(instr, operands) => operands
+            .iter()
+            .enumerate()
+            .any(|(i, o)| instr.is_store(i + 1) && *o == Operand::Position(addr + 2)),
+        Op::Data(_) => false,
+    });
+    let target = match operands[1] {
+        Operand::Immediate(target) if !patched => Some(target),
+        _ => None,
+    };
+    let always = match operands[0] {
+        Operand::Immediate(cond) if (cond != 0) == (instr == JmpIfTrue) => true,
+        Operand::Immediate(_) => return (Exit::Next(end), vec![*addr]),
+        _ => false,
+    };
+    let push = block
+        .ops
+        .iter()
+        .find(|(_, op)| constant(op) == Some((end, Operand::Relative(0))));
+    match (always, target, push) {
+        (true, _, Some((push, _))) => (Exit::Call { target, next: end }, vec![*push, *addr]),
+        (true, None, None) if operands[1] == Operand::Relative(0) => {
+            let mut hidden = vec![*addr];
+            if let [.., (adjust, Op::Instr(AdjustBase, args)), _] = &block.ops[..] {
+                if matches!(args[0], Operand::Immediate(off) if off < 0) {
+                    hidden.push(*adjust);
+                }
+            }
+            (Exit::Return, hidden)
+        }
+        (true, Some(target), None) => (Exit::Goto(target), vec![*addr]),
+        (false, Some(target), _) => (Exit::Branch { target, next: end }, vec![*addr]),
+        // Indirect jumps are shown as statements
+        (_, None, _) => (if always { Exit::Stop } else { Exit::Next(end) }, vec![]),
+    }
+}
+
+// Negation of a comparison or of a test against zero, operands may contain
+// comparisons themselves when they index memory
+fn negate(cond: &str) -> String {
+    for (op, neg) in &[
+        (" < ", " >= "),
+        (" >= ", " < "),
+        (" == ", " != "),
+        (" != ", " == "),
+    ] {
+        let top = cond.match_indices(op).find(|(idx, _)| {
+            let prefix = &cond[..*idx];
+            prefix.matches(['[', '(']).count() == prefix.matches([']', ')']).count()
+        });
+        if let Some((idx, _)) = top {
+            return format!("{}{}{}", &cond[..idx], neg, &cond[idx + op.len()..]);
+        }
+    }
+    format!("!({})", cond)
+}
+
+struct Writer<'a> {
+    blocks: &'a BTreeMap<Intcode, Block>,
+    exits: &'a BTreeMap<Intcode, (Exit, Vec<Intcode>)>,
+    frame: Intcode,
+    // AdjustBase starting the function, implied by the frame size
+    prologue: Option<Intcode>,
+    order: Vec<Intcode>,
+    lines: Vec<Line>,
+    gotos: BTreeSet<Intcode>,
+    depth: usize,
+    // Header and exit of the loops being written, innermost last
+    loops: Vec<(Intcode, Intcode)>,
+    // Where the then part of the if/else being written continues
+    joins: Vec<Intcode>,
+}
+
+impl<'a> Writer<'a> {
+    fn text(&mut self, text: String) {
+        self.lines.push(Line::Text(self.depth, text));
+    }
+
+    fn index_at(&self, addr: Intcode) -> usize {
+        self.order.partition_point(|&start| start < addr)
+    }
+
+    fn slot(&self, off: Intcode) -> String {
+        if off > 0 {
+            format!("arg{}", off)
+        } else if -self.frame < off && off < 0 {
+            format!("v{}", self.frame + off)
+        } else {
+            format!("rb[{}]", off)
+        }
+    }
+
+    // Operand of the instruction at addr, the words of operands written to by
+    // earlier instructions of the block are given as expressions in patches
+    fn operand(
+        &self,
+        patches: &BTreeMap<Intcode, String>,
+        word: Intcode,
+        operand: Operand,
+    ) -> String {
+        match (patches.get(&word), operand) {
+            (Some(expr), Operand::Position(_)) => format!("mem[{}]", expr),
+            (Some(expr), Operand::Immediate(_)) => format!("({})", expr),
+            (Some(expr), Operand::Relative(_)) => format!("rb[{}]", expr),
+            (None, Operand::Position(addr)) => format!("mem[{}]", addr),
+            (None, Operand::Immediate(val)) => val.to_string(),
+            (None, Operand::Relative(off)) => self.slot(off),
+        }
+    }
+
+    // Statements of the block and the condition for its last jump to be taken
+    fn statements(&self, start: Intcode) -> (Vec<String>, Option<String>) {
+        let block = &self.blocks[&start];
+        let (exit, hidden) = &self.exits[&start];
+        let mut patches = BTreeMap::new();
+        let mut lines = Vec::new();
+        let last = block.ops.last().unwrap().0;
+        let shown = |addr: &Intcode| {
+            let is_call = *addr == last && matches!(exit, Exit::Call { .. });
+            is_call || !hidden.contains(addr) && self.prologue != Some(*addr)
+        };
+        for (addr, op) in block.ops.iter().filter(|(addr, _)| shown(addr)) {
+            let (instr, operands) = match op {
+                Op::Instr(instr, operands) => (*instr, operands),
+                Op::Data(_) => continue,
+            };
+            let arg = |i: usize| self.operand(&patches, addr + 1 + i as Intcode, operands[i]);
+            let imm = |i: usize| match operands[i] {
+                Operand::Immediate(val) if !patches.contains_key(&(addr + 1 + i as Intcode)) => {
+                    Some(val)
+                }
+                _ => None,
+            };
+            let expr = match instr {
+                Add if imm(1) == Some(0) => arg(0),
+                Add if imm(0) == Some(0) => arg(1),
+                Add => match imm(1) {
+                    Some(val) if val < 0 => format!("{} - {}", arg(0), -val),
+                    _ => format!("{} + {}", arg(0), arg(1)),
+                },
+                Mul if imm(1) == Some(1) => arg(0),
+                Mul if imm(0) == Some(1) => arg(1),
+                Mul if imm(1) == Some(-1) => format!("-{}", arg(0)),
+                Mul => format!("{} * {}", arg(0), arg(1)),
+                LessThan => format!("{} < {}", arg(0), arg(1)),
+                Equals => format!("{} == {}", arg(0), arg(1)),
+                In => {
+                    lines.push(format!("{} = input()", arg(0)));
+                    continue;
+                }
+                Out => {
+                    lines.push(format!("output({})", arg(0)));
+                    continue;
+                }
+                AdjustBase => {
+                    lines.push(format!("rb += {}", arg(0)));
+                    continue;
+                }
+                Halt => {
+                    lines.push("halt".to_string());
+                    continue;
+                }
+                _ => {
+                    lines.push(match (exit, operands[0]) {
+                        (
+                            Exit::Call {
+                                target: Some(target),
+                                ..
+                            },
+                            _,
+                        ) => format!("call f{}", target),
+                        (Exit::Call { .. }, _) => match (patches.get(&(addr + 2)), operands[1]) {
+                            (Some(expr), Operand::Immediate(_)) => format!("call *{}", expr),
+                            _ => format!("call *{}", arg(1)),
+                        },
+                        (_, Operand::Immediate(_)) => format!("goto *{}", arg(1)),
+                        _ => format!(
+                            "if {} {{ goto *{} }}",
+                            self.taken(block, *addr, &patches),
+                            arg(1)
+                        ),
+                    });
+                    continue;
+                }
+            };
+            // A store to an operand later in the block, usually to index an
+            // array, is folded into that operand
+            if let Operand::Position(word) = operands[2] {
+                let is_operand = block
+                    .ops
+                    .iter()
+                    .any(|(a, op)| a < &word && word < a + op.num_words() as Intcode && a > addr);
+                if is_operand {
+                    patches.insert(word, expr);
+                    continue;
+                }
+            }
+            lines.push(format!("{} = {}", arg(2), expr));
+        }
+        let cond = match exit {
+            Exit::Branch { .. } => Some(self.taken(block, last, &patches)),
+            _ => None,
+        };
+        (lines, cond)
+    }
+
+    // Condition for the jump at addr to be taken, a comparison stored to the
+    // tested operand by the instruction before is given as the comparison.
+    // Not if it overwrote one of its inputs, those no longer hold the values
+    // compared.
+    fn taken(&self, block: &Block, addr: Intcode, patches: &BTreeMap<Intcode, String>) -> String {
+        let pos = block.ops.iter().position(|(a, _)| *a == addr).unwrap();
+        let (instr, cond) = match &block.ops[pos].1 {
+            Op::Instr(instr, operands) => (*instr, operands[0]),
+            Op::Data(_) => unreachable!(),
+        };
+        let test = match pos.checked_sub(1).map(|prev| &block.ops[prev]) {
+            Some((prev, Op::Instr(cmp @ (LessThan | Equals), operands)))
+                if operands[2] == cond && !operands[..2].contains(&cond) =>
+            {
+                let arg = |i: usize| self.operand(patches, prev + 1 + i as Intcode, operands[i]);
+                let op = if *cmp == LessThan { "<" } else { "==" };
+                format!("{} {} {}", arg(0), op, arg(1))
+            }
+            _ => format!("{} != 0", self.operand(patches, addr + 1, cond)),
+        };
+        if instr == JmpIfTrue {
+            test
+        } else {
+            negate(&test)
+        }
+    }
+
+    // Continues at target, nothing is written when that is where execution
+    // ends up anyway
+    fn jump(&mut self, target: Intcode, end: Intcode, is_last: bool) {
+        match self.loops.last() {
+            Some(&(header, exit)) if target == header => {
+                if !(is_last && end == exit) {
+                    self.text("continue".to_string());
+                }
+                return;
+            }
+            Some(&(_, exit)) if target == exit => {
+                self.text("break".to_string());
+                return;
+            }
+            _ => (),
+        }
+        if is_last && (target == end || self.joins.last() == Some(&target)) {
+            return;
+        }
+        if self.exits.get(&target).map(|(exit, _)| exit) == Some(&Exit::Return)
+            && self.statements(target).0.is_empty()
+        {
+            self.text("return".to_string());
+            return;
+        }
+        self.gotos.insert(target);
+        self.text(format!("goto L{}", target));
+    }
+
+    // Index of the last block in the range with a jump back to the block at
+    // index idx
+    fn back_edge(&self, idx: usize, end: Intcode) -> Option<usize> {
+        let header = self.order[idx];
+        (idx..self.index_at(end)).rev().find(|&j| {
+            matches!(self.exits[&self.order[j]].0,
+                Exit::Goto(target) | Exit::Branch { target, .. } if target == header)
+        })
+    }
+
+    // Writes the blocks starting at index idx and ending before address end
+    fn range(&mut self, mut idx: usize, end: Intcode, mut in_loop: bool) {
+        while idx < self.order.len() && self.order[idx] < end {
+            let start = self.order[idx];
+            if !in_loop {
+                if let Some(tail) = self.back_edge(idx, end) {
+                    let exit = self.blocks[&self.order[tail]].end();
+                    self.text("loop {".to_string());
+                    self.depth += 1;
+                    self.loops.push((start, exit));
+                    self.range(idx, exit, true);
+                    self.loops.pop();
+                    self.depth -= 1;
+                    self.text("}".to_string());
+                    idx = self.index_at(exit);
+                    continue;
+                }
+            }
+            in_loop = false;
+            self.lines.push(Line::Label(start));
+            let (lines, cond) = self.statements(start);
+            for line in lines {
+                self.text(line);
+            }
+            idx += 1;
+            let following = self.order.get(idx).cloned().filter(|&addr| addr < end);
+            let is_last = following.is_none();
+            match self.exits[&start].0 {
+                Exit::Next(next) | Exit::Call { next, .. } => {
+                    if following != Some(next) {
+                        self.jump(next, end, is_last);
+                    }
+                }
+                Exit::Goto(target) => self.jump(target, end, is_last),
+                Exit::Branch { target, next } => {
+                    let taken = cond.unwrap();
+                    let keyword = match self.loops.last() {
+                        Some(&(header, _)) if target == header => Some("continue"),
+                        Some(&(_, exit)) if target == exit => Some("break"),
+                        _ => None,
+                    };
+                    let loop_end = self.loops.last().map(|&(_, exit)| exit);
+                    if keyword == Some("continue") && is_last && loop_end == Some(next) {
+                        // Condition at the end of the loop
+                        self.text(format!("if {} {{ break }}", negate(&taken)));
+                        continue;
+                    } else if let Some(keyword) = keyword {
+                        self.text(format!("if {} {{ {} }}", taken, keyword));
+                    } else if following == Some(next) && target > next && target <= end {
+                        let k = self.index_at(target);
+                        let join = match self.exits[&self.order[k - 1]].0 {
+                            Exit::Goto(join) if join > target && join <= end => Some(join),
+                            _ => None,
+                        }
+                        .filter(|&join| self.loops.iter().all(|&(h, e)| join != h && join != e));
+                        self.text(format!("if {} {{", negate(&taken)));
+                        self.depth += 1;
+                        match join {
+                            Some(join) => {
+                                self.joins.push(join);
+                                self.range(idx, target, false);
+                                self.joins.pop();
+                                self.depth -= 1;
+                                self.text("} else {".to_string());
+                                self.depth += 1;
+                                self.range(k, join, false);
+                                idx = self.index_at(join);
+                            }
+                            None => {
+                                self.range(idx, target, false);
+                                idx = k;
+                            }
+                        }
+                        self.depth -= 1;
+                        self.text("}".to_string());
+                        continue;
+                    } else {
+                        self.gotos.insert(target);
+                        self.text(format!("if {} {{ goto L{} }}", taken, target));
+                    }
+                    if following != Some(next) {
+                        self.jump(next, end, is_last);
+                    }
+                }
+                Exit::Return => self.text("return".to_string()),
+                Exit::Stop => (),
+            }
+        }
+    }
+}
+
+// Functions start at address 0 and at the targets of calls, a function is
+// made up of the blocks reachable from its start without following calls
+fn functions(
+    analysis: &Analysis,
+    exits: &BTreeMap<Intcode, (Exit, Vec<Intcode>)>,
+) -> Vec<Function> {
+    let blocks = analysis.blocks();
+    let mut entries = BTreeSet::new();
+    entries.insert(0);
+    for (exit, _) in exits.values() {
+        if let Exit::Call {
+            target: Some(target),
+            ..
+        } = exit
+        {
+            entries.insert(*target);
+        }
+    }
+    let mut functions = Vec::new();
+    for entry in entries.into_iter().filter(|e| blocks.contains_key(e)) {
+        let frame = match blocks[&entry].ops[0].1 {
+            Op::Instr(AdjustBase, ref operands) if entry != 0 => match operands[0] {
+                Operand::Immediate(size) if size > 0 => size,
+                _ => 0,
+            },
+            _ => 0,
+        };
+        let mut body = BTreeSet::new();
+        let mut work = vec![entry];
+        while let Some(start) = work.pop() {
+            if !blocks.contains_key(&start) || !body.insert(start) {
+                continue;
+            }
+            match exits[&start].0 {
+                Exit::Next(next) | Exit::Goto(next) | Exit::Call { next, .. } => work.push(next),
+                Exit::Branch { target, next } => work.extend(&[target, next]),
+                Exit::Return | Exit::Stop => (),
+            }
+        }
+        functions.push(Function {
+            entry,
+            frame,
+            blocks: body.into_iter().collect(),
+        });
+    }
+    functions
+}
+
+pub fn decompile(program: &[Intcode]) -> String {
+    let analysis = analyze(program);
+    let blocks = analysis.blocks();
+    let exits = blocks
+        .iter()
+        .map(|(&start, block)| (start, exit(block)))
+        .collect::<BTreeMap<_, _>>();
+    let mut text = String::new();
+    for func in functions(&analysis, &exits) {
+        let mut writer = Writer {
+            blocks,
+            exits: &exits,
+            frame: func.frame,
+            prologue: Some(func.entry).filter(|_| func.frame > 0),
+            order: func.blocks,
+            lines: Vec::new(),
+            gotos: BTreeSet::new(),
+            depth: 1,
+            loops: Vec::new(),
+            joins: Vec::new(),
+        };
+        if writer.order[0] != func.entry {
+            writer.gotos.insert(func.entry);
+            writer.text(format!("goto L{}", func.entry));
+        }
+        writer.range(0, Intcode::MAX, false);
+        if func.entry == 0 {
+            writeln!(text, "fn main() {{").unwrap();
+        } else {
+            writeln!(text, "fn f{}(frame {}) {{", func.entry, func.frame).unwrap();
+        }
+        for line in &writer.lines {
+            match line {
+                Line::Label(addr) if writer.gotos.contains(addr) => {
+                    writeln!(text, "L{}:", addr).unwrap()
+                }
+                Line::Label(_) => (),
+                Line::Text(depth, line) => {
+                    writeln!(text, "{}{}", "    ".repeat(*depth), line).unwrap()
+                }
+            }
+        }
+        writeln!(text, "}}\n").unwrap();
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SUM: &str = "; Sums the numbers up to the input, negated if the sum is 100 or more
+                              AdjustBase #stack
+                              In [n]
+                              Add [n], #0, rb+1
+                              Add #ret, #0, rb+0
+                              JmpIfTrue #1, #sum
+                       ret:   Out rb+1
+                              Halt
+                       sum:   AdjustBase #3
+                              Add #0, #0, rb-1
+                       loop:  JmpIfFalse rb-2, #done
+                              Add rb-1, rb-2, rb-1
+                              Add rb-2, #-1, rb-2
+                              JmpIfTrue #1, #loop
+                       done:  LessThan rb-1, #100, rb-2
+                              JmpIfTrue rb-2, #small
+                              Mul rb-1, #-1, rb-2
+                              JmpIfTrue #1, #end
+                       small: Add rb-1, #0, rb-2
+                       end:   AdjustBase #-3
+                              JmpIfTrue #1, rb+0
+                       n:     Data 0
+                       stack: Data 0";
+
+    #[test]
+    fn decompile_sum() {
+        let program = assemble(SUM).unwrap();
+        assert_eq!(
+            decompile(&program),
+            "fn main() {\n\
+             \x20   rb += 62\n\
+             \x20   mem[61] = input()\n\
+             \x20   arg1 = mem[61]\n\
+             \x20   call f18\n\
+             \x20   output(arg1)\n\
+             \x20   halt\n\
+             }\n\
+             \n\
+             fn f18(frame 3) {\n\
+             \x20   v2 = 0\n\
+             \x20   loop {\n\
+             \x20       if v1 == 0 { break }\n\
+             \x20       v2 = v2 + v1\n\
+             \x20       v1 = v1 - 1\n\
+             \x20   }\n\
+             \x20   v1 = v2 < 100\n\
+             \x20   if v2 >= 100 {\n\
+             \x20       v1 = -v2\n\
+             \x20   } else {\n\
+             \x20       v1 = v2\n\
+             \x20   }\n\
+             \x20   return\n\
+             }\n\
+             \n"
+        );
+        let mut vm = Vm::new(&program);
+        vm.push_input(20);
+        assert_eq!(vm.output(), Ok(Some(-210)));
+    }
+
+    const IN_PLACE: &str = "; Outputs 1 if the input is less than 10, else 0
+                                   In [val]
+                                   LessThan [val], #10, [val]
+                                   JmpIfTrue [val], #small
+                                   Out #0
+                                   Halt
+                            small: Out #1
+                                   Halt
+                            val:   Data 0";
+
+    #[test]
+    fn decompile_in_place() {
+        // The comparison overwrites its input, so the jump tests its result
+        assert_eq!(
+            decompile(&assemble(IN_PLACE).unwrap()),
+            "fn main() {\n\
+             \x20   mem[15] = input()\n\
+             \x20   mem[15] = mem[15] < 10\n\
+             \x20   if mem[15] == 0 {\n\
+             \x20       output(0)\n\
+             \x20       halt\n\
+             \x20   }\n\
+             \x20   output(1)\n\
+             \x20   halt\n\
+             }\n\
+             \n"
+        );
+    }
+}
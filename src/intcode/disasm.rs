@@ -0,0 +1,142 @@
+use super::*;
+
+// Parameter of a decoded instruction, formatted as "[pos]", "#imm" or "rb+off"
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operand {
+    Position(Intcode),
+    Immediate(Intcode),
+    Relative(Intcode),
+}
+
+// Content of a single address range in a disassembled program
+#[derive(Clone, Debug, PartialEq)]
+pub enum Op {
+    Instr(Instruction, Vec<Operand>),
+    Data(Intcode),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Line {
+    pub addr: Intcode,
+    pub op: Op,
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Operand::Position(addr) => write!(f, "[{}]", addr),
+            Operand::Immediate(val) => write!(f, "#{}", val),
+            Operand::Relative(off) if off < 0 => write!(f, "rb{}", off),
+            Operand::Relative(off) => write!(f, "rb+{}", off),
+        }
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Op::Instr(instr, operands) => {
+                write!(f, "{:?}", instr)?;
+                for (i, operand) in operands.iter().enumerate() {
+                    write!(f, "{}{}", if i == 0 { " " } else { ", " }, operand)?;
+                }
+                Ok(())
+            }
+            Op::Data(val) => write!(f, "Data {}", val),
+        }
+    }
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:>6}: {}", self.addr, self.op)
+    }
+}
+
+impl Op {
+    // Number of words the operation occupies in memory
+    pub fn num_words(&self) -> usize {
+        match self {
+            Op::Instr(_, operands) => 1 + operands.len(),
+            Op::Data(_) => 1,
+        }
+    }
+}
+
+// Decodes the instruction at addr, None is returned if the word isn't a valid
+// opcode, uses an invalid address mode or if the parameters don't fit in the
+// program
+pub fn decode(program: &[Intcode], addr: usize) -> Option<Op> {
+    let opcode = program[addr];
+    let instr = to_instr(opcode)?;
+    if addr + instr.num_params() >= program.len() {
+        return None;
+    }
+    let mut operands = Vec::new();
+    for offset in 1..=instr.num_params() {
+        let val = program[addr + offset];
+        operands.push(match to_mode(opcode, offset as Intcode)? {
+            Position => Operand::Position(val),
+            Immediate if instr.is_store(offset) => return None,
+            Immediate => Operand::Immediate(val),
+            Relative => Operand::Relative(val),
+        });
+    }
+    Some(Op::Instr(instr, operands))
+}
+
+// Linear sweep disassembly of a program, words that can't be decoded as an
+// instruction are treated as data
+pub fn disassemble(program: &[Intcode]) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut addr = 0;
+    while addr < program.len() {
+        let op = decode(program, addr).unwrap_or(Op::Data(program[addr]));
+        let len = op.num_words();
+        lines.push(Line {
+            addr: addr as Intcode,
+            op,
+        });
+        addr += len;
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn listing(program: &[Intcode]) -> Vec<String> {
+        disassemble(program).iter().map(|l| l.to_string()).collect()
+    }
+
+    #[test]
+    fn disasm_modes() {
+        assert_eq!(
+            listing(&[1002, 4, 3, 4, 33, 109, -1, 204, 3, 21101, 1, 2, -4, 99]),
+            vec![
+                "     0: Mul [4], #3, [4]",
+                "     4: Data 33",
+                "     5: AdjustBase #-1",
+                "     7: Out rb+3",
+                "     9: Add #1, #2, rb-4",
+                "    13: Halt",
+            ]
+        );
+    }
+
+    #[test]
+    fn disasm_data() {
+        assert_eq!(
+            listing(&[104, 7, 42, 11101, 99, 2, 1]),
+            vec![
+                "     0: Out #7",
+                "     2: Data 42",
+                "     3: Data 11101",
+                "     4: Halt",
+                "     5: Data 2",
+                "     6: Data 1",
+            ]
+        );
+    }
+}
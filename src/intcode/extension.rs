@@ -0,0 +1,281 @@
+use super::*;
+
+// Parameter of an instruction added with Vm::extend(). Read parameters are
+// loaded like the operands of Add, write parameters give the address to store
+// at like its last operand, so the same address modes apply.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Param {
+    Read,
+    Write,
+}
+
+// What the machine does after executing an added instruction
+#[derive(Debug, PartialEq)]
+pub enum Effect<W = Intcode> {
+    // Stores the values at the write parameters in order and continues with
+    // the next instruction
+    Next(Vec<W>),
+    Jump(Intcode),
+}
+
+// Instruction added to the instruction set of a machine. It is given the
+// values of its read parameters, an error makes the machine fault with the
+// message. Observers don't see added instructions being executed, only the
+// values they store.
+pub trait Extension<W = Intcode>: Send {
+    fn params(&self) -> Vec<Param>;
+
+    fn exec(&mut self, args: &[W]) -> Result<Effect<W>, String>;
+}
+
+pub type SharedExtension<W = Intcode> = Arc<Mutex<dyn Extension<W>>>;
+
+// Opcodes of the instructions added by Vm::extra_instructions()
+pub const PRINT: Intcode = 90;
+pub const ASSERT: Intcode = 91;
+pub const CMP: Intcode = 92;
+pub const JMP: Intcode = 93;
+
+// Prints its operand on stderr, leaving the output of the program alone
+pub struct Print;
+
+impl<W: Word> Extension<W> for Print {
+    fn params(&self) -> Vec<Param> {
+        vec![Param::Read]
+    }
+
+    fn exec(&mut self, args: &[W]) -> Result<Effect<W>, String> {
+        eprintln!("Print: {}", args[0]);
+        Ok(Effect::Next(Vec::new()))
+    }
+}
+
+// Faults unless its two operands are equal
+pub struct Assert;
+
+impl<W: Word> Extension<W> for Assert {
+    fn params(&self) -> Vec<Param> {
+        vec![Param::Read, Param::Read]
+    }
+
+    fn exec(&mut self, args: &[W]) -> Result<Effect<W>, String> {
+        if args[0] == args[1] {
+            Ok(Effect::Next(Vec::new()))
+        } else {
+            Err(format!("assertion failed, {} != {}", args[0], args[1]))
+        }
+    }
+}
+
+// Stores -1, 0 or 1 as the first operand is less than, equal to or greater
+// than the second
+pub struct Cmp;
+
+impl<W: Word> Extension<W> for Cmp {
+    fn params(&self) -> Vec<Param> {
+        vec![Param::Read, Param::Read, Param::Write]
+    }
+
+    fn exec(&mut self, args: &[W]) -> Result<Effect<W>, String> {
+        let order = if args[0] < args[1] {
+            -1
+        } else if args[0] == args[1] {
+            0
+        } else {
+            1
+        };
+        Ok(Effect::Next(vec![W::from_intcode(order)]))
+    }
+}
+
+// Jumps to its operand unconditionally
+pub struct Jmp;
+
+impl<W: Word> Extension<W> for Jmp {
+    fn params(&self) -> Vec<Param> {
+        vec![Param::Read]
+    }
+
+    fn exec(&mut self, args: &[W]) -> Result<Effect<W>, String> {
+        match args[0].to_intcode() {
+            Some(target) => Ok(Effect::Jump(target)),
+            None => Err(format!("jump to invalid address {}", args[0])),
+        }
+    }
+}
+
+// Whether an instruction can be added with opcode, it must fit in the two
+// lowest digits and not be the opcode of a standard instruction
+pub(super) fn is_free(opcode: Intcode) -> bool {
+    (1..100).contains(&opcode) && to_instr(opcode).is_none()
+}
+
+impl<W: Word> Vm<W> {
+    // Adds an instruction with opcode to the instruction set, opcodes of the
+    // standard instructions can't be replaced. Clones of the machine share
+    // the added instructions.
+    pub fn extend(&mut self, opcode: Intcode, ext: impl Extension<W> + 'static) {
+        assert!(is_free(opcode), "Opcode {} is not free", opcode);
+        self.extensions.insert(opcode, Arc::new(Mutex::new(ext)));
+    }
+
+    // Adds Print and Assert for testing hand-written programs, and the Cmp
+    // and Jmp instructions Intcode lacks
+    pub fn extra_instructions(&mut self) {
+        self.extend(PRINT, Print);
+        self.extend(ASSERT, Assert);
+        self.extend(CMP, Cmp);
+        self.extend(JMP, Jmp);
+    }
+
+    // Executes an added instruction, or faults if the opcode isn't known
+    pub(super) fn step_extension(&mut self) -> Result<Option<Event<W>>, IntcodeError> {
+        let (ip, opcode) = (self.ip, self.opcode());
+        let ext = match self.extensions.get(&(opcode % 100)) {
+            Some(ext) => ext.clone(),
+            None => return Err(IntcodeError::InvalidInstruction { ip, opcode }),
+        };
+        if self.recording.is_some() {
+            self.record_step();
+        }
+        if self.executed >= self.next_check {
+            self.check_limits()?;
+        }
+        self.executed += 1;
+        let mut ext = ext.lock().unwrap();
+        let params = ext.params();
+        let mut args = Vec::new();
+        let mut addrs = Vec::new();
+        for (offset, param) in (1..).zip(&params) {
+            let mode = to_mode(opcode, offset).ok_or(IntcodeError::InvalidMode { ip, opcode })?;
+            match param {
+                Param::Read => args.push(self.load(mode, offset)?),
+                Param::Write => addrs.push(self.store_addr(mode, offset)?),
+            }
+        }
+        let fail = |message| IntcodeError::Extension {
+            ip,
+            opcode,
+            message,
+        };
+        let effect = ext.exec(&args).map_err(fail)?;
+        self.ip = match effect {
+            Effect::Next(vals) => {
+                if vals.len() != addrs.len() {
+                    return Err(fail(format!(
+                        "{} values stored by instruction with {} write parameters",
+                        vals.len(),
+                        addrs.len()
+                    )));
+                }
+                for (addr, val) in addrs.into_iter().zip(vals) {
+                    self.store(addr, val);
+                }
+                ip + 1 + params.len() as Intcode
+            }
+            Effect::Jump(target) => target,
+        };
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Stores the quotient and the remainder of the first operand divided by
+    // the second
+    struct DivMod;
+
+    impl Extension for DivMod {
+        fn params(&self) -> Vec<Param> {
+            vec![Param::Read, Param::Read, Param::Write, Param::Write]
+        }
+
+        fn exec(&mut self, args: &[Intcode]) -> Result<Effect, String> {
+            match args {
+                [_, 0] => Err("division by zero".to_string()),
+                [a, b] => Ok(Effect::Next(vec![a / b, a % b])),
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    const DIGITS: &str = "; Outputs the digits of the input from the last one, checks the sum
+                          loop: In [val]
+                          next: Ext 42, [val], #10, [val], rb+0
+                                Out rb+0
+                                Add [sum], rb+0, [sum]
+                                JmpIfTrue [val], #next
+                                Ext 91, [sum], #6
+                                Halt
+                          val:  Data 0
+                          sum:  Data 0";
+
+    #[test]
+    fn ext_divmod() {
+        let program = assemble(DIGITS).unwrap();
+        let mut vm = Vm::new(&program);
+        vm.extend(42, DivMod);
+        vm.extra_instructions();
+        vm.push_input(123);
+        assert_eq!(vm.clone().output(), Ok(Some(3)));
+        let mut output = Vec::new();
+        assert_eq!(
+            vm.run_io(&mut iter::empty(), &mut output),
+            Ok(Event::Halted)
+        );
+        assert_eq!(output, vec![3, 2, 1]);
+        vm = Vm::new(&program);
+        vm.extend(42, DivMod);
+        vm.extra_instructions();
+        vm.push_input(124);
+        let err = vm.run_io(&mut iter::empty(), &mut Vec::new()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "assertion failed, 7 != 6 at ip 16 (opcode 1091)"
+        );
+    }
+
+    const SIGN: &str = "; Outputs the sign of each input value
+                        loop: In [val]
+                              Ext 92, [val], #0, [val]
+                              Out [val]
+                              Ext 93, #loop
+                        val:  Data 0";
+
+    #[test]
+    fn ext_extra() {
+        let mut vm = Vm::new(&assemble(SIGN).unwrap());
+        vm.extra_instructions();
+        let mut output = Vec::new();
+        let mut input = vec![-7, 0, 12].into_iter();
+        assert_eq!(vm.run_io(&mut input, &mut output), Ok(Event::NeedsInput));
+        assert_eq!(output, vec![-1, 0, 1]);
+    }
+
+    #[test]
+    fn ext_errors() {
+        let program = assemble(DIGITS).unwrap();
+        assert_eq!(Vm::new(&program).run(), Ok(Event::NeedsInput));
+        let mut vm = Vm::new(&program);
+        vm.push_input(5);
+        assert_eq!(
+            vm.run(),
+            Err(IntcodeError::InvalidInstruction {
+                ip: 2,
+                opcode: 201042
+            })
+        );
+        vm.extend(42, DivMod);
+        vm.poke(4, 0);
+        assert_eq!(
+            vm.run(),
+            Err(IntcodeError::Extension {
+                ip: 2,
+                opcode: 201042,
+                message: "division by zero".to_string()
+            })
+        );
+    }
+}
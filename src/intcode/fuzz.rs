@@ -0,0 +1,503 @@
+use super::memory::FLAT_LIMIT;
+use super::*;
+use std::collections::BTreeMap;
+
+// Differential testing of the virtual machine. Random programs are run both on
+// Vm and on a reference interpreter that follows the puzzle description to the
+// letter, and the output, final memory and way the run stopped are compared.
+// A program on which they disagree is shrunk before it is reported.
+
+// Number of instructions a program may execute, most random programs loop
+// forever. Programs that run long enough use the decode cache of Vm.
+const BUDGET: u64 = 2 * CACHE_WARMUP;
+
+// Size of the random programs
+const MAX_INSTRS: u64 = 12;
+const DATA_WORDS: usize = 6;
+
+// Xorshift generator, reproducible from its seed
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Rng {
+        Rng(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+
+    fn range(&mut self, lo: Intcode, hi: Intcode) -> Intcode {
+        lo + self.below((hi - lo + 1) as u64) as Intcode
+    }
+
+    // True once in n times
+    fn one_in(&mut self, n: u64) -> bool {
+        self.below(n) == 0
+    }
+
+    // Address in the sparse part of memory, near one of a few bases so that
+    // instructions get to share cells
+    fn far(&mut self) -> Intcode {
+        let bases = [-(1 << 20), -8, FLAT_LIMIT as Intcode, 1 << 40];
+        bases[self.below(bases.len() as u64) as usize] + self.range(0, 3)
+    }
+
+    // Any value at all, arithmetic on these mostly wraps around
+    fn large(&mut self) -> Intcode {
+        self.next() as Intcode
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Case {
+    program: Vec<Intcode>,
+    input: Vec<Intcode>,
+}
+
+// How a run ended, the output it produced and the cells of memory that don't
+// read as zero
+#[derive(Debug, PartialEq)]
+struct Outcome {
+    exit: Result<Event, IntcodeError>,
+    output: Vec<Intcode>,
+    memory: BTreeMap<Intcode, Intcode>,
+}
+
+// Number of parameters of the standard opcodes
+fn num_params(opcode: Intcode) -> usize {
+    match opcode {
+        1 | 2 | 7 | 8 => 3,
+        5 | 6 => 2,
+        3 | 4 | 9 => 1,
+        _ => 0,
+    }
+}
+
+// Random program of valid instructions followed by Halt and some data. Jumps
+// go to the start of an instruction and nothing is stored in immediate mode,
+// but the program may still modify itself into something invalid. Operands
+// mostly refer to the program itself, now and then they refer to the sparse
+// part of memory or are large enough to overflow.
+fn generate(rng: &mut Rng) -> Case {
+    const OPCODES: [Intcode; 10] = [1, 2, 3, 4, 5, 6, 7, 8, 9, 99];
+    let opcodes = (0..1 + rng.below(MAX_INSTRS))
+        .map(|_| OPCODES[rng.below(OPCODES.len() as u64) as usize])
+        .collect::<Vec<_>>();
+    let mut starts = vec![0];
+    for &opcode in &opcodes {
+        starts.push(starts.last().unwrap() + 1 + num_params(opcode) as Intcode);
+    }
+    let size = starts.last().unwrap() + 1 + DATA_WORDS as Intcode;
+    let mut program = Vec::new();
+    for &opcode in &opcodes {
+        let mut instr = opcode;
+        let mut operands = Vec::new();
+        for n in 0..num_params(opcode) {
+            let store = matches!((opcode, n), (1, 2) | (2, 2) | (7, 2) | (8, 2) | (3, 0));
+            let target = matches!((opcode, n), (5, 1) | (6, 1));
+            let mode = if store {
+                2 * rng.below(2)
+            } else {
+                rng.below(3)
+            };
+            operands.push(match mode {
+                1 if target => starts[rng.below(starts.len() as u64) as usize],
+                _ if rng.one_in(8) => rng.far(),
+                1 if rng.one_in(8) => rng.large(),
+                0 => rng.range(0, size - 1),
+                1 => rng.range(-3, 10),
+                _ => rng.range(-4, 4),
+            });
+            instr += mode as Intcode * pow(10, n + 2);
+        }
+        program.push(instr);
+        program.extend(operands);
+    }
+    program.push(99);
+    program.extend((0..DATA_WORDS).map(|_| match rng.below(8) {
+        0 => rng.far(),
+        1 => rng.large(),
+        _ => rng.range(-5, 110),
+    }));
+    let input = (0..rng.below(4)).map(|_| rng.range(-5, 20)).collect();
+    Case { program, input }
+}
+
+// Interpreter written straight from the puzzle description, without caching
+// or generic words. Faults are reported the way Vm reports them.
+struct Reference {
+    mem: HashMap<Intcode, Intcode>,
+    ip: Intcode,
+    relative_base: Intcode,
+    opcode: Intcode,
+}
+
+impl Reference {
+    fn peek(&self, addr: Intcode) -> Intcode {
+        self.mem.get(&addr).cloned().unwrap_or(0)
+    }
+
+    // Address of parameter n, None if it is in immediate mode
+    fn addr(&self, n: u32) -> Result<Option<Intcode>, IntcodeError> {
+        let val = self.peek(self.ip + n as Intcode);
+        match self.opcode / 10i64.pow(n + 1) % 10 {
+            0 => Ok(Some(val)),
+            1 => Ok(None),
+            2 => Ok(Some(self.relative_base.wrapping_add(val))),
+            _ => Err(IntcodeError::InvalidMode {
+                ip: self.ip,
+                opcode: self.opcode,
+            }),
+        }
+    }
+
+    fn read(&self, n: u32) -> Result<Intcode, IntcodeError> {
+        let addr = self.addr(n)?.unwrap_or(self.ip + n as Intcode);
+        Ok(self.peek(addr))
+    }
+
+    fn write(&mut self, n: u32, val: Intcode) -> Result<(), IntcodeError> {
+        match self.addr(n)? {
+            Some(addr) => {
+                self.mem.insert(addr, val);
+                Ok(())
+            }
+            None => Err(IntcodeError::ImmediateStore {
+                ip: self.ip,
+                opcode: self.opcode,
+            }),
+        }
+    }
+
+    fn run(
+        &mut self,
+        input: &mut VecDeque<Intcode>,
+        output: &mut Vec<Intcode>,
+        budget: u64,
+    ) -> Result<Event, IntcodeError> {
+        let mut executed = 0;
+        loop {
+            let ip = self.ip;
+            self.opcode = self.peek(ip);
+            let opcode = self.opcode;
+            let instr = opcode % 100;
+            if !(1..=9).contains(&instr) && instr != 99 {
+                return Err(IntcodeError::InvalidInstruction { ip, opcode });
+            }
+            if instr == 3 && input.is_empty() {
+                return Ok(Event::NeedsInput);
+            }
+            if executed == budget {
+                return Err(IntcodeError::BudgetExceeded {
+                    ip,
+                    opcode,
+                    budget: Budget::Instructions(budget),
+                });
+            }
+            executed += 1;
+            self.ip = match instr {
+                1 | 2 | 7 | 8 => {
+                    let (a, b) = (self.read(1)?, self.read(2)?);
+                    let val = match instr {
+                        1 => a.wrapping_add(b),
+                        2 => a.wrapping_mul(b),
+                        7 => (a < b) as Intcode,
+                        _ => (a == b) as Intcode,
+                    };
+                    self.write(3, val)?;
+                    ip + 4
+                }
+                3 => {
+                    self.write(1, input[0])?;
+                    input.pop_front();
+                    ip + 2
+                }
+                4 => {
+                    output.push(self.read(1)?);
+                    ip + 2
+                }
+                5 | 6 => {
+                    if (self.read(1)? != 0) == (instr == 5) {
+                        self.read(2)?
+                    } else {
+                        ip + 3
+                    }
+                }
+                9 => {
+                    self.relative_base = self.relative_base.wrapping_add(self.read(1)?);
+                    ip + 2
+                }
+                _ => return Ok(Event::Halted),
+            };
+        }
+    }
+}
+
+fn reference(case: &Case, budget: u64) -> Outcome {
+    let mut vm = Reference {
+        mem: (0..).zip(case.program.iter().cloned()).collect(),
+        ip: 0,
+        relative_base: 0,
+        opcode: 0,
+    };
+    let mut output = Vec::new();
+    let exit = vm.run(
+        &mut case.input.iter().cloned().collect(),
+        &mut output,
+        budget,
+    );
+    let memory = vm.mem.into_iter().filter(|&(_, val)| val != 0).collect();
+    Outcome {
+        exit,
+        output,
+        memory,
+    }
+}
+
+// Output of a program run on the reference interpreter until it halts or
+// needs more input than given. Memory is a HashMap and every instruction is
+// decoded when it is executed, like in the interpreter Vm replaced, so this
+// is the baseline when benchmarking Vm.
+pub fn run_reference(program: &[Intcode], input: &[Intcode]) -> Result<Vec<Intcode>, IntcodeError> {
+    let case = Case {
+        program: program.to_vec(),
+        input: input.to_vec(),
+    };
+    let Outcome { exit, output, .. } = reference(&case, u64::MAX);
+    exit.map(|_| output)
+}
+
+// Runs the case on Vm. With cached the machine counts as having run long
+// enough to set up its decode cache, so self-modifying code is also tested
+// against cached instructions.
+fn execute(case: &Case, budget: u64, cached: bool) -> Outcome {
+    let mut vm = Vm::new(&case.program);
+    if cached {
+        vm.executed = CACHE_WARMUP;
+    }
+    vm.limit_instructions(Some(budget));
+    let mut output = Vec::new();
+    let exit = vm.run_io(&mut case.input.iter().cloned(), &mut output);
+    Outcome {
+        exit,
+        output,
+        memory: vm.mem.cells().collect(),
+    }
+}
+
+// Run of Vm that differs from the reference, and whether the decode cache was
+// set up from the start
+fn mismatch(case: &Case) -> Option<(Outcome, bool)> {
+    let expected = reference(case, BUDGET);
+    [false, true]
+        .iter()
+        .map(|&cached| (execute(case, BUDGET, cached), cached))
+        .find(|(actual, _)| *actual != expected)
+}
+
+fn diverges(case: &Case) -> bool {
+    mismatch(case).is_some()
+}
+
+// Cases are ordered by their number of words first, then by the magnitude of
+// the values
+fn size(case: &Case) -> (usize, u64) {
+    let words = case.program.iter().chain(&case.input);
+    (
+        case.program.len() + case.input.len(),
+        words.map(|word| word.unsigned_abs()).sum(),
+    )
+}
+
+// Variants of a case with a run of words removed or a word replaced by a
+// simpler one
+fn candidates(case: &Case) -> Vec<Case> {
+    let mut cases = Vec::new();
+    let mut chunk = case.program.len();
+    while chunk > 0 {
+        for start in (0..case.program.len()).step_by(chunk) {
+            let mut smaller = case.clone();
+            let end = (start + chunk).min(smaller.program.len());
+            smaller.program.drain(start..end);
+            cases.push(smaller);
+        }
+        chunk /= 2;
+    }
+    for idx in 0..case.input.len() {
+        let mut smaller = case.clone();
+        smaller.input.remove(idx);
+        cases.push(smaller);
+    }
+    for idx in 0..case.program.len() + case.input.len() {
+        for simpler in &[
+            0,
+            1,
+            99,
+            case.program.iter().chain(&case.input).nth(idx).unwrap() / 2,
+        ] {
+            let mut smaller = case.clone();
+            match idx.checked_sub(case.program.len()) {
+                Some(idx) => smaller.input[idx] = *simpler,
+                None => smaller.program[idx] = *simpler,
+            }
+            cases.push(smaller);
+        }
+    }
+    cases
+}
+
+// Makes a failing case smaller for as long as it keeps failing
+fn shrink(mut case: Case, fails: impl Fn(&Case) -> bool) -> Case {
+    while let Some(smaller) = candidates(&case)
+        .into_iter()
+        .find(|c| size(c) < size(&case) && fails(c))
+    {
+        case = smaller;
+    }
+    case
+}
+
+// Program on which Vm and the reference interpreter disagree, with how the run
+// went on each of them
+pub struct Divergence {
+    case: Case,
+    expected: Outcome,
+    actual: Outcome,
+    cached: bool,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.exit {
+            Ok(event) => writeln!(f, "  Stopped: {:?}", event)?,
+            Err(err) => writeln!(f, "  Stopped: {}", err)?,
+        }
+        writeln!(f, "  Output: {:?}", self.output)?;
+        let cells = self
+            .memory
+            .iter()
+            .map(|(addr, val)| format!("[{}]={}", addr, val))
+            .collect::<Vec<_>>();
+        writeln!(f, "  Memory: {}", cells.join(" "))
+    }
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Program:")?;
+        for line in disassemble(&self.case.program) {
+            writeln!(f, "{}", line)?;
+        }
+        writeln!(f, "Input: {:?}", self.case.input)?;
+        let cache = if self.cached {
+            "decode cache set up from the start"
+        } else {
+            "from a fresh start"
+        };
+        write!(
+            f,
+            "Reference:\n{}Vm, {}:\n{}",
+            self.expected, cache, self.actual
+        )
+    }
+}
+
+// Runs the given number of random programs, returning the first divergence
+// found after shrinking it
+pub fn fuzz(rounds: usize, seed: u64) -> Option<Divergence> {
+    let mut rng = Rng::new(seed);
+    let case = (0..rounds).map(|_| generate(&mut rng)).find(diverges)?;
+    let case = shrink(case, diverges);
+    let (actual, cached) = mismatch(&case).unwrap();
+    Some(Divergence {
+        expected: reference(&case, BUDGET),
+        actual,
+        cached,
+        case,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STACK: &str = "; Pushes the input on a stack until a zero, then pops and outputs it
+                                AdjustBase #stack
+                         push:  In rb+0
+                                JmpIfFalse rb+0, #pop
+                                AdjustBase #1
+                                JmpIfTrue #1, #push
+                         pop:   AdjustBase #-1
+                                JmpIfFalse rb+0, #end
+                                Out rb+0
+                                JmpIfTrue #1, #pop
+                         end:   Halt
+                         empty: Data 0
+                         stack: Data 0";
+
+    #[test]
+    fn fuzz_reference() {
+        let case = Case {
+            program: assemble(STACK).unwrap(),
+            input: vec![3, 1, 4, 0],
+        };
+        let outcome = reference(&case, BUDGET);
+        assert_eq!(outcome.exit, Ok(Event::Halted));
+        assert_eq!(outcome.output, vec![4, 1, 3]);
+        assert_eq!(outcome, execute(&case, BUDGET, false));
+        assert_eq!(outcome, execute(&case, BUDGET, true));
+        let outcome = reference(&case, 10);
+        assert_eq!(
+            outcome.exit.unwrap_err().to_string(),
+            "budget of 10 instructions exceeded at ip 4 (opcode 1206)"
+        );
+    }
+
+    #[test]
+    fn fuzz_sparse() {
+        let mut rng = Rng::new(1);
+        let outcomes = (0..500)
+            .map(|_| reference(&generate(&mut rng), BUDGET))
+            .collect::<Vec<_>>();
+        let stored = |far: fn(Intcode) -> bool| {
+            outcomes
+                .iter()
+                .any(|outcome| outcome.memory.keys().any(|&addr| far(addr)))
+        };
+        assert!(stored(|addr| addr < 0));
+        assert!(stored(|addr| addr >= FLAT_LIMIT as Intcode));
+    }
+
+    #[test]
+    fn fuzz_agree() {
+        if let Some(divergence) = fuzz(2000, 2019) {
+            panic!("Vm differs from the reference\n{}", divergence);
+        }
+    }
+
+    #[test]
+    fn fuzz_shrink() {
+        let case = Case {
+            program: assemble(STACK).unwrap(),
+            input: vec![3, 1, 4, 0],
+        };
+        // Pushes the input and outputs it, no word can be removed or made
+        // smaller without losing the output
+        let shrunk = shrink(case, |c| reference(c, BUDGET).output.contains(&3));
+        assert_eq!(
+            shrunk,
+            Case {
+                program: vec![203, 0, 1206, 0, 0, 204],
+                input: vec![3]
+            }
+        );
+    }
+}
@@ -0,0 +1,95 @@
+use super::*;
+
+// Source of the values read by In instructions. Every iterator is an input,
+// so vectors are read with into_iter(), channels with iter() and closures
+// with std::iter::from_fn().
+pub trait Input<W = Intcode> {
+    // None if no more input will ever be available
+    fn read(&mut self) -> Option<W>;
+}
+
+// Destination of the values written by Out instructions
+pub trait Output<W = Intcode> {
+    // Returns false if the value could not be delivered and no more output
+    // will be accepted
+    fn write(&mut self, val: W) -> bool;
+}
+
+impl<W, I: Iterator<Item = W>> Input<W> for I {
+    fn read(&mut self) -> Option<W> {
+        self.next()
+    }
+}
+
+impl<W> Output<W> for Vec<W> {
+    fn write(&mut self, val: W) -> bool {
+        self.push(val);
+        true
+    }
+}
+
+impl<W> Output<W> for Sender<W> {
+    fn write(&mut self, val: W) -> bool {
+        self.send(val).is_ok()
+    }
+}
+
+impl<W, F: FnMut(W)> Output<W> for F {
+    fn write(&mut self, val: W) -> bool {
+        self(val);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::iter;
+
+    // Outputs the sum of each pair of values read
+    const ADD_PAIRS: [Intcode; 15] = [3, 13, 3, 14, 1, 13, 14, 13, 4, 13, 1105, 1, 0, 0, 0];
+
+    fn add_pairs() -> Vm {
+        Vm::new(&ADD_PAIRS)
+    }
+
+    #[test]
+    fn io_vec() {
+        let mut output = Vec::new();
+        let event = add_pairs().run_io(&mut vec![1, 2, 3, 4].into_iter(), &mut output);
+        assert_eq!(event, Ok(Event::NeedsInput));
+        assert_eq!(output, vec![3, 7]);
+    }
+
+    #[test]
+    fn io_closures() {
+        let mut next = 0;
+        let mut input = iter::from_fn(|| {
+            next += 1;
+            Some(next)
+        });
+        let mut sums = Vec::new();
+        let event = add_pairs().run_io(&mut input.by_ref().take(6), &mut |val| sums.push(val));
+        assert_eq!(event, Ok(Event::NeedsInput));
+        assert_eq!(sums, vec![3, 7, 11]);
+        assert_eq!(input.next(), Some(7));
+    }
+
+    #[test]
+    fn io_channels() {
+        let (tx, rx) = channel();
+        let (mut out_tx, out_rx) = channel();
+        for val in &[5, 6] {
+            tx.send(*val).unwrap();
+        }
+        drop(tx);
+        assert_eq!(
+            add_pairs().run_io(&mut rx.iter(), &mut out_tx),
+            Ok(Event::NeedsInput)
+        );
+        assert_eq!(out_rx.try_iter().collect::<Vec<_>>(), vec![11]);
+        drop(out_rx);
+        let event = Vm::new(&[104, 1, 104, 2, 99]).run_io(&mut rx.iter(), &mut out_tx);
+        assert_eq!(event, Ok(Event::Output(1)));
+    }
+}
@@ -0,0 +1,113 @@
+use super::{Intcode, Word};
+use std::collections::HashMap;
+
+// Addresses below this limit are kept in a contiguous vector that grows on
+// demand, everything else (including negative addresses) is kept sparse
+pub(super) const FLAT_LIMIT: usize = 1 << 16;
+
+// Memory of a virtual machine, cells that never have been written to reads as
+// zero
+#[derive(Clone)]
+pub struct Memory<W = Intcode> {
+    flat: Vec<W>,
+    sparse: HashMap<Intcode, W>,
+}
+
+impl<W: Word> Memory<W> {
+    pub fn new(program: &[W]) -> Memory<W> {
+        Memory {
+            flat: program.to_vec(),
+            sparse: HashMap::new(),
+        }
+    }
+
+    pub fn get(&self, addr: Intcode) -> W {
+        if let Some(val) = self.flat.get(addr as usize) {
+            if addr >= 0 {
+                return val.clone();
+            }
+        }
+        match self.sparse.get(&addr) {
+            Some(val) => val.clone(),
+            None => W::from_intcode(0),
+        }
+    }
+
+    pub fn set(&mut self, addr: Intcode, val: W) {
+        if addr < 0 || addr as usize >= FLAT_LIMIT.max(self.flat.len()) {
+            self.sparse.insert(addr, val);
+            return;
+        }
+        let idx = addr as usize;
+        if idx >= self.flat.len() {
+            let new_len = (idx + 1).max(2 * self.flat.len()).min(FLAT_LIMIT);
+            self.flat.resize(new_len, W::from_intcode(0));
+        }
+        self.flat[idx] = val;
+    }
+
+    // Number of cells kept in the contiguous vector, the program image and
+    // whatever has been written right after it
+    pub fn flat_len(&self) -> usize {
+        self.flat.len()
+    }
+
+    // Addresses and values of all cells that don't read as zero
+    pub fn cells(&self) -> impl Iterator<Item = (Intcode, W)> + '_ {
+        let flat = (0..).zip(self.flat.iter().cloned());
+        let sparse = self.sparse.iter().map(|(&addr, val)| (addr, val.clone()));
+        flat.chain(sparse).filter(|(_, val)| !val.is_zero())
+    }
+}
+
+// Memories are equal when every cell reads the same, whether it is kept in the
+// contiguous vector or not
+impl<W: Word> PartialEq for Memory<W> {
+    fn eq(&self, other: &Memory<W>) -> bool {
+        let flat = 0..self.flat.len().max(other.flat.len()) as Intcode;
+        let sparse = self.sparse.keys().chain(other.sparse.keys()).cloned();
+        flat.chain(sparse)
+            .all(|addr| self.get(addr) == other.get(addr))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mem_unwritten() {
+        let mem: Memory = Memory::new(&[1, 2, 3]);
+        assert_eq!(mem.get(2), 3);
+        assert_eq!(mem.get(3), 0);
+        assert_eq!(mem.get(-1), 0);
+        assert_eq!(mem.get(Intcode::MAX), 0);
+    }
+
+    #[test]
+    fn mem_grow() {
+        let mut mem: Memory = Memory::new(&[1, 2, 3]);
+        mem.set(1000, 42);
+        mem.set(-5, 7);
+        mem.set(1 << 40, 9);
+        assert_eq!(mem.flat.len(), 1001);
+        assert_eq!(mem.sparse.len(), 2);
+        assert_eq!(mem.get(1000), 42);
+        assert_eq!(mem.get(999), 0);
+        assert_eq!(mem.get(-5), 7);
+        assert_eq!(mem.get(1 << 40), 9);
+        assert_eq!(mem.get(2), 3);
+    }
+
+    #[test]
+    fn mem_eq() {
+        let mut mem: Memory = Memory::new(&[1, 2, 3]);
+        let mut grown = mem.clone();
+        grown.set(1000, 0);
+        assert!(mem == grown);
+        mem.set(-5, 7);
+        assert!(mem != grown);
+        grown.set(-5, 7);
+        assert!(mem == grown);
+    }
+}
@@ -0,0 +1,934 @@
+use extension::SharedExtension;
+use memory::Memory;
+use recording::Recording;
+use num_derive::FromPrimitive;
+use num_traits::*;
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::iter;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::*;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use AddressMode::*;
+use Instruction::*;
+
+mod analysis;
+mod ascii;
+mod asm;
+mod capture;
+mod circuit;
+mod debugger;
+mod decompile;
+mod disasm;
+mod extension;
+mod fuzz;
+mod io;
+mod memory;
+mod network;
+mod profile;
+mod program;
+mod recording;
+mod terminal;
+mod word;
+
+pub use analysis::analyze;
+pub use ascii::AsciiMachine;
+pub use asm::assemble;
+pub use capture::{capture_all, Record, Replay};
+pub use circuit::Circuit;
+pub use debugger::Debugger;
+pub use decompile::decompile;
+pub use disasm::disassemble;
+pub use fuzz::{fuzz, run_reference};
+pub use network::{Nat, Network, Packet, Verdict};
+pub use io::{Input, Output};
+pub use profile::{Profile, Tracer};
+pub use program::Program;
+pub use terminal::Terminal;
+pub use word::Word;
+
+pub type Intcode = i64;
+
+#[derive(Clone, Copy, Debug, FromPrimitive, PartialEq)]
+pub enum AddressMode {
+    Position = 0,
+    Immediate = 1,
+    Relative = 2,
+}
+
+#[derive(Clone, Copy, Debug, Eq, FromPrimitive, Hash, PartialEq)]
+pub enum Instruction {
+    Add = 1,
+    Mul = 2,
+    In = 3,
+    Out = 4,
+    JmpIfTrue = 5,
+    JmpIfFalse = 6,
+    LessThan = 7,
+    Equals = 8,
+    AdjustBase = 9,
+    Halt = 99,
+}
+
+impl Instruction {
+    // Number of parameters that follows the opcode
+    pub fn num_params(self) -> usize {
+        match self {
+            Add | Mul | LessThan | Equals => 3,
+            JmpIfTrue | JmpIfFalse => 2,
+            In | Out | AdjustBase => 1,
+            Halt => 0,
+        }
+    }
+
+    // Returns true if the parameter at offset (starting at 1) is the address
+    // the instruction stores its result to
+    pub fn is_store(self, offset: usize) -> bool {
+        match self {
+            Add | Mul | LessThan | Equals => offset == 3,
+            In => offset == 1,
+            _ => false,
+        }
+    }
+}
+
+// Reason the virtual machine stopped executing
+#[derive(Debug, PartialEq)]
+pub enum Event<W = Intcode> {
+    NeedsInput,
+    Output(W),
+    Halted,
+}
+
+// Faults detected while executing a program, each variant records the address
+// of the faulting instruction and its opcode
+#[derive(Clone, Debug, PartialEq)]
+pub enum IntcodeError {
+    InvalidMode { ip: Intcode, opcode: Intcode },
+    InvalidInstruction { ip: Intcode, opcode: Intcode },
+    ImmediateStore { ip: Intcode, opcode: Intcode },
+    BudgetExceeded { ip: Intcode, opcode: Intcode, budget: Budget },
+    Cancelled { ip: Intcode, opcode: Intcode },
+    Overflow { ip: Intcode, opcode: Intcode },
+    Extension { ip: Intcode, opcode: Intcode, message: String },
+}
+
+// Limit on how much a virtual machine may execute, see Vm::limit_instructions()
+// and Vm::limit_time()
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Budget {
+    Instructions(u64),
+    Time(Duration),
+}
+
+impl IntcodeError {
+    pub fn ip(&self) -> Intcode {
+        match *self {
+            IntcodeError::InvalidMode { ip, .. } => ip,
+            IntcodeError::InvalidInstruction { ip, .. } => ip,
+            IntcodeError::ImmediateStore { ip, .. } => ip,
+            IntcodeError::BudgetExceeded { ip, .. } => ip,
+            IntcodeError::Cancelled { ip, .. } => ip,
+            IntcodeError::Overflow { ip, .. } => ip,
+            IntcodeError::Extension { ip, .. } => ip,
+        }
+    }
+
+    pub fn opcode(&self) -> Intcode {
+        match *self {
+            IntcodeError::InvalidMode { opcode, .. } => opcode,
+            IntcodeError::InvalidInstruction { opcode, .. } => opcode,
+            IntcodeError::ImmediateStore { opcode, .. } => opcode,
+            IntcodeError::BudgetExceeded { opcode, .. } => opcode,
+            IntcodeError::Cancelled { opcode, .. } => opcode,
+            IntcodeError::Overflow { opcode, .. } => opcode,
+            IntcodeError::Extension { opcode, .. } => opcode,
+        }
+    }
+}
+
+impl fmt::Display for IntcodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let reason = match self {
+            IntcodeError::InvalidMode { .. } => "invalid address mode".to_string(),
+            IntcodeError::InvalidInstruction { .. } => "invalid instruction".to_string(),
+            IntcodeError::ImmediateStore { .. } => "store with immediate address mode".to_string(),
+            IntcodeError::BudgetExceeded { budget, .. } => match budget {
+                Budget::Instructions(max) => format!("budget of {} instructions exceeded", max),
+                Budget::Time(max) => format!("time limit of {:?} exceeded", max),
+            },
+            IntcodeError::Cancelled { .. } => "cancelled".to_string(),
+            IntcodeError::Overflow { .. } => "arithmetic overflow".to_string(),
+            IntcodeError::Extension { message, .. } => message.clone(),
+        };
+        write!(
+            f,
+            "{} at ip {} (opcode {})",
+            reason,
+            self.ip(),
+            self.opcode()
+        )
+    }
+}
+
+impl Error for IntcodeError {}
+
+// Receives every instruction executed by the virtual machines it is attached
+// to. Operands are resolved, i.e. a load is given as the loaded value and a
+// store as the address written to.
+pub trait Observer: Send {
+    fn instruction(&mut self, ip: Intcode, instr: Instruction, operands: &[Intcode]);
+
+    fn store(&mut self, _addr: Intcode, _val: Intcode) {}
+}
+
+pub type SharedObserver = Arc<Mutex<dyn Observer>>;
+
+static DEFAULT_OBSERVER: Mutex<Option<SharedObserver>> = Mutex::new(None);
+
+// Attaches an observer to every virtual machine created from now on, or stops
+// doing so if observer is None
+pub fn observe_all(observer: Option<SharedObserver>) {
+    *DEFAULT_OBSERVER.lock().unwrap() = observer;
+}
+
+static DECODE_CACHE: AtomicBool = AtomicBool::new(true);
+
+// Enables or disables caching of decoded instructions in virtual machines
+// that haven't started caching yet, only useful to measure how much the cache
+// helps
+pub fn use_decode_cache(enable: bool) {
+    DECODE_CACHE.store(enable, Ordering::Relaxed);
+}
+
+// Opcode split into instruction and the address modes of its parameters. A
+// mode is None if the digit isn't a valid mode, that is only an error if the
+// instruction actually use that parameter.
+#[derive(Clone, Copy)]
+struct Decoded {
+    instr: Instruction,
+    modes: [Option<AddressMode>; 3],
+}
+
+// Intcode virtual machine that executes on the thread that calls run(). A
+// clone is a snapshot of the complete machine state, including memory and
+// any queued input, that can be run independently of the original. Clones
+// share the observer of the original. Memory holds Intcode values unless
+// another word type is given, see Vm::from_words().
+#[derive(Clone)]
+pub struct Vm<W = Intcode> {
+    mem: Memory<W>,
+    ip: Intcode,
+    relative_base: Intcode,
+    input: VecDeque<W>,
+    observer: Option<SharedObserver>,
+    // Decoded instructions keyed by address, covers the contiguous part of
+    // memory. Empty until the machine has run for CACHE_WARMUP instructions.
+    decoded: Vec<Option<Decoded>>,
+    // Number of instructions executed and when the limits must be checked
+    executed: u64,
+    next_check: u64,
+    max_executed: Option<(u64, Budget)>,
+    deadline: Option<(Instant, Budget)>,
+    cancel: Option<Arc<AtomicBool>>,
+    checked: bool,
+    recording: Option<Box<Recording<W>>>,
+    // Instructions added with extend(), by opcode
+    extensions: HashMap<Intcode, SharedExtension<W>>,
+}
+
+// Instructions executed before a machine starts caching decoded instructions.
+// Short-lived machines, like the drones of day 19, execute few instructions
+// more than once, for them setting up the cache costs more than it saves.
+const CACHE_WARMUP: u64 = 1000;
+
+// Number of instructions executed between checks of the clock, and of whether
+// the machine has been cancelled
+const CHECK_INTERVAL: u64 = 4096;
+
+fn to_mode(opcode: Intcode, position: Intcode) -> Option<AddressMode> {
+    FromPrimitive::from_i64(opcode / pow(10, (position + 1) as usize) % 10)
+}
+
+fn to_instr(opcode: Intcode) -> Option<Instruction> {
+    FromPrimitive::from_i64(opcode % 100)
+}
+
+impl Decoded {
+    fn new(opcode: Intcode) -> Option<Decoded> {
+        Some(Decoded {
+            instr: to_instr(opcode)?,
+            modes: [to_mode(opcode, 1), to_mode(opcode, 2), to_mode(opcode, 3)],
+        })
+    }
+}
+
+impl Vm {
+    pub fn new(program: &[Intcode]) -> Vm {
+        Vm::from_words(program)
+    }
+}
+
+impl<W: Word> Vm<W> {
+    // Machine with memory of any word type, e.g. i128 or BigInt for programs
+    // that need more than 64 bits
+    pub fn from_words(program: &[W]) -> Vm<W> {
+        Vm {
+            mem: Memory::new(program),
+            ip: 0,
+            relative_base: 0,
+            input: VecDeque::new(),
+            observer: DEFAULT_OBSERVER.lock().unwrap().clone(),
+            decoded: Vec::new(),
+            executed: 0,
+            next_check: u64::MAX,
+            max_executed: None,
+            deadline: None,
+            cancel: None,
+            checked: false,
+            recording: None,
+            extensions: HashMap::new(),
+        }
+    }
+
+    // Replaces the observer, nothing is observed when observer is None.
+    // Observers see values that don't fit in an Intcode as the closest
+    // Intcode.
+    pub fn attach(&mut self, observer: Option<SharedObserver>) {
+        self.observer = observer;
+    }
+
+    // Makes Add, Mul and AdjustBase fail with Overflow instead of wrapping
+    // around when the result doesn't fit in a word
+    pub fn check_overflow(&mut self, enable: bool) {
+        self.checked = enable;
+    }
+
+    // Queues a value that will be consumed by a later In instruction
+    pub fn push_input(&mut self, val: W) {
+        self.input.push_back(val);
+    }
+
+    pub fn ip(&self) -> Intcode {
+        self.ip
+    }
+
+    pub fn relative_base(&self) -> Intcode {
+        self.relative_base
+    }
+
+    // Number of values queued for In instructions
+    pub fn pending_input(&self) -> usize {
+        self.input.len()
+    }
+
+    // Whether the machines will do the same from now on: same memory,
+    // instruction pointer, relative base and queued input
+    fn same_state(&self, other: &Vm<W>) -> bool {
+        self.ip == other.ip
+            && self.relative_base == other.relative_base
+            && self.input == other.input
+            && self.mem == other.mem
+    }
+
+    // Reads memory, addresses that never have been written to reads as zero.
+    // Memory stays intact after the program halts.
+    pub fn peek(&self, addr: Intcode) -> W {
+        self.mem.get(addr)
+    }
+
+    // Writes memory without executing any instruction, e.g. to patch the
+    // program before it starts
+    pub fn poke(&mut self, addr: Intcode, val: W) {
+        self.mem.set(addr, val);
+        // Self-modifying code, the old decoding is no longer valid
+        if addr >= 0 {
+            if let Some(dec) = self.decoded.get_mut(addr as usize) {
+                *dec = None;
+            }
+        }
+    }
+
+    // Makes the machine fail with BudgetExceeded when it is about to execute
+    // more than max instructions from now on, None removes the limit. The
+    // instruction is not executed so the machine can continue with a new
+    // budget.
+    pub fn limit_instructions(&mut self, max: Option<u64>) {
+        self.max_executed = max.map(|max| (self.executed + max, Budget::Instructions(max)));
+        self.schedule_check();
+    }
+
+    // Like limit_instructions(), but for the time spent from now on. The clock
+    // is only checked now and then, so the limit may be overrun slightly.
+    pub fn limit_time(&mut self, max: Option<Duration>) {
+        self.deadline = max.map(|max| (Instant::now() + max, Budget::Time(max)));
+        self.schedule_check();
+    }
+
+    fn schedule_check(&mut self) {
+        let periodic = self.deadline.is_some() || self.cancel.is_some();
+        self.next_check = self
+            .max_executed
+            .map_or(u64::MAX, |(max, _)| max)
+            .min(if periodic {
+                self.executed + CHECK_INTERVAL
+            } else {
+                u64::MAX
+            });
+    }
+
+    fn check_limits(&mut self) -> Result<(), IntcodeError> {
+        let exceeded = |budget| IntcodeError::BudgetExceeded {
+            ip: self.ip,
+            opcode: self.opcode(),
+            budget,
+        };
+        if let Some((max, budget)) = self.max_executed {
+            if self.executed >= max {
+                return Err(exceeded(budget));
+            }
+        }
+        if let Some((deadline, budget)) = self.deadline {
+            if Instant::now() >= deadline {
+                return Err(exceeded(budget));
+            }
+        }
+        if self
+            .cancel
+            .as_ref()
+            .is_some_and(|c| c.load(Ordering::Relaxed))
+        {
+            return Err(IntcodeError::Cancelled {
+                ip: self.ip,
+                opcode: self.opcode(),
+            });
+        }
+        self.schedule_check();
+        Ok(())
+    }
+
+    // Opcode at ip as reported in errors
+    fn opcode(&self) -> Intcode {
+        self.peek(self.ip).saturate()
+    }
+
+    fn overflow(&self) -> IntcodeError {
+        IntcodeError::Overflow {
+            ip: self.ip,
+            opcode: self.opcode(),
+        }
+    }
+
+    // Address val relative to base, it is an overflow if the address doesn't
+    // fit in an Intcode. The sum wraps around unless overflow is checked.
+    fn address(&self, base: Intcode, val: &W) -> Result<Intcode, IntcodeError> {
+        let addr = val.to_intcode().and_then(|val| {
+            if self.checked {
+                base.checked_add(val)
+            } else {
+                Some(base.wrapping_add(val))
+            }
+        });
+        addr.ok_or_else(|| self.overflow())
+    }
+
+    // Decodes the instruction at ip, or gets it from the cache if it has been
+    // decoded before
+    fn fetch(&mut self) -> Result<Decoded, IntcodeError> {
+        let cached = self.decoded.get(self.ip as usize).filter(|_| self.ip >= 0);
+        if let Some(&Some(dec)) = cached {
+            return Ok(dec);
+        }
+        let dec = self.peek(self.ip).to_intcode().and_then(Decoded::new);
+        let dec = dec.ok_or_else(|| IntcodeError::InvalidInstruction {
+            ip: self.ip,
+            opcode: self.opcode(),
+        })?;
+        if cached.is_some() {
+            self.decoded[self.ip as usize] = Some(dec);
+        } else if self.executed >= CACHE_WARMUP
+            && self.decoded.is_empty()
+            && DECODE_CACHE.load(Ordering::Relaxed)
+        {
+            self.decoded = vec![None; self.mem.flat_len()];
+        }
+        Ok(dec)
+    }
+
+    fn mode(&self, dec: Decoded, offset: Intcode) -> Result<AddressMode, IntcodeError> {
+        dec.modes[offset as usize - 1].ok_or_else(|| IntcodeError::InvalidMode {
+            ip: self.ip,
+            opcode: self.opcode(),
+        })
+    }
+
+    fn ld(&self, dec: Decoded, offset: Intcode) -> Result<W, IntcodeError> {
+        self.load(self.mode(dec, offset)?, offset)
+    }
+
+    fn load(&self, mode: AddressMode, offset: Intcode) -> Result<W, IntcodeError> {
+        let val = self.peek(self.ip + offset);
+        Ok(match mode {
+            Position => self.peek(self.address(0, &val)?),
+            Immediate => val,
+            Relative => self.peek(self.address(self.relative_base, &val)?),
+        })
+    }
+
+    fn st_addr(&self, dec: Decoded, offset: Intcode) -> Result<Intcode, IntcodeError> {
+        self.store_addr(self.mode(dec, offset)?, offset)
+    }
+
+    fn store_addr(&self, mode: AddressMode, offset: Intcode) -> Result<Intcode, IntcodeError> {
+        let imm = self.peek(self.ip + offset);
+        match mode {
+            Position => self.address(0, &imm),
+            Immediate => Err(IntcodeError::ImmediateStore {
+                ip: self.ip,
+                opcode: self.opcode(),
+            }),
+            Relative => self.address(self.relative_base, &imm),
+        }
+    }
+
+    fn st(&mut self, dec: Decoded, offset: Intcode, val: W) -> Result<(), IntcodeError> {
+        let addr = self.st_addr(dec, offset)?;
+        self.store(addr, val);
+        Ok(())
+    }
+
+    fn store(&mut self, addr: Intcode, val: W) {
+        if let Some(observer) = &self.observer {
+            observer.lock().unwrap().store(addr, val.saturate());
+        }
+        self.poke(addr, val);
+    }
+
+    // Resolves all operands of an instruction in the way observers expects them
+    fn resolve(&self, dec: Decoded) -> Result<Vec<Intcode>, IntcodeError> {
+        (1..=dec.instr.num_params() as Intcode)
+            .map(|offset| {
+                if dec.instr.is_store(offset as usize) {
+                    self.st_addr(dec, offset)
+                } else {
+                    Ok(self.ld(dec, offset)?.saturate())
+                }
+            })
+            .collect()
+    }
+
+    fn binop(
+        &mut self,
+        dec: Decoded,
+        op: impl Fn(&W, &W) -> Option<W>,
+    ) -> Result<Intcode, IntcodeError> {
+        let val = op(&self.ld(dec, 1)?, &self.ld(dec, 2)?).ok_or_else(|| self.overflow())?;
+        self.st(dec, 3, val)?;
+        Ok(self.ip + 4)
+    }
+
+    fn arith(
+        &mut self,
+        dec: Decoded,
+        checked_op: fn(&W, &W) -> Option<W>,
+        wrapping_op: fn(&W, &W) -> W,
+    ) -> Result<Intcode, IntcodeError> {
+        if self.checked {
+            self.binop(dec, checked_op)
+        } else {
+            self.binop(dec, |a, b| Some(wrapping_op(a, b)))
+        }
+    }
+
+    fn jmp_if(&self, dec: Decoded, pred: fn(&W) -> bool) -> Result<Intcode, IntcodeError> {
+        Ok(if pred(&self.ld(dec, 1)?) {
+            self.address(0, &self.ld(dec, 2)?)?
+        } else {
+            self.ip + 3
+        })
+    }
+
+    // Executes a single instruction. An event is returned if the instruction
+    // produced output, halted or tried to read input when the input queue is
+    // empty. The instruction pointer is left at the In instruction when input
+    // is needed, so stepping again after push_input() continues where the
+    // program stopped. A faulting instruction is not executed either, so the
+    // instruction pointer is left pointing at it.
+    pub fn step(&mut self) -> Result<Option<Event<W>>, IntcodeError> {
+        let dec = match self.fetch() {
+            Err(IntcodeError::InvalidInstruction { .. }) if !self.extensions.is_empty() => {
+                return self.step_extension();
+            }
+            dec => dec?,
+        };
+        if dec.instr == In && self.input.is_empty() {
+            return Ok(Some(Event::NeedsInput));
+        }
+        if self.recording.is_some() {
+            self.record_step();
+        }
+        if self.executed >= self.next_check {
+            self.check_limits()?;
+        }
+        self.executed += 1;
+        if let Some(observer) = &self.observer {
+            let operands = self.resolve(dec)?;
+            observer
+                .lock()
+                .unwrap()
+                .instruction(self.ip, dec.instr, &operands);
+        }
+        let bool_word = |cond: bool| W::from_intcode(if cond { 1 } else { 0 });
+        self.ip = match dec.instr {
+            Add => self.arith(dec, W::checked_add, W::wrapping_add)?,
+            Mul => self.arith(dec, W::checked_mul, W::wrapping_mul)?,
+            In => {
+                self.st(dec, 1, self.input[0].clone())?;
+                if let Some(rec) = &mut self.recording {
+                    rec.log_input(self.input[0].clone());
+                }
+                self.input.pop_front();
+                self.ip + 2
+            }
+            Out => {
+                let val = self.ld(dec, 1)?;
+                self.ip += 2;
+                return Ok(Some(Event::Output(val)));
+            }
+            JmpIfTrue => self.jmp_if(dec, |a| !a.is_zero())?,
+            JmpIfFalse => self.jmp_if(dec, |a| a.is_zero())?,
+            LessThan => self.binop(dec, |a, b| Some(bool_word(a < b)))?,
+            Equals => self.binop(dec, |a, b| Some(bool_word(a == b)))?,
+            AdjustBase => {
+                self.relative_base = self.address(self.relative_base, &self.ld(dec, 1)?)?;
+                self.ip + 2
+            }
+            Halt => return Ok(Some(Event::Halted)),
+        };
+        Ok(None)
+    }
+
+    // Executes instructions until the program produces an output value, tries
+    // to read input when the input queue is empty or halts
+    pub fn run(&mut self) -> Result<Event<W>, IntcodeError> {
+        loop {
+            if let Some(event) = self.step()? {
+                return Ok(event);
+            }
+        }
+    }
+
+    // Runs until the next output value, None is returned if the program halts
+    // or blocks waiting for input before producing any output.
+    pub fn output(&mut self) -> Result<Option<W>, IntcodeError> {
+        Ok(match self.run()? {
+            Event::Output(val) => Some(val),
+            _ => None,
+        })
+    }
+
+    // Runs until the program halts, reading from input whenever the program
+    // needs it and writing every value output. Also stops when input runs out,
+    // giving NeedsInput, or when output refuses a value, giving that value.
+    pub fn run_io<I: Input<W>, O: Output<W>>(
+        &mut self,
+        input: &mut I,
+        output: &mut O,
+    ) -> Result<Event<W>, IntcodeError> {
+        loop {
+            match self.run()? {
+                Event::NeedsInput => match input.read() {
+                    Some(val) => self.push_input(val),
+                    None => return Ok(Event::NeedsInput),
+                },
+                Event::Output(val) => {
+                    if !output.write(val.clone()) {
+                        return Ok(Event::Output(val));
+                    }
+                }
+                Event::Halted => return Ok(Event::Halted),
+            }
+        }
+    }
+}
+
+// How a virtual machine running on its own thread stopped
+#[derive(Debug, PartialEq)]
+pub enum Exit {
+    Halted,
+    InputClosed,
+    OutputClosed,
+    Cancelled,
+    Fault(IntcodeError),
+}
+
+// Handle to a virtual machine running on its own thread. Dropping the handle
+// leaves the thread running.
+pub struct VmThread {
+    cancel: Arc<AtomicBool>,
+    handle: JoinHandle<Exit>,
+}
+
+// How often a thread waiting for input checks if it has been cancelled
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+impl VmThread {
+    // Asks the machine to stop, also if it is waiting for input
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+
+    // Waits until the machine has stopped
+    pub fn join(self) -> Exit {
+        self.handle.join().unwrap()
+    }
+}
+
+// Runs the machine on a new thread that reads input from and writes output to
+// channels. The thread stops when the program halts, faults, runs out of
+// budget or is cancelled, or when either channel is disconnected.
+pub fn spawn(
+    vm: Vm,
+    input: Receiver<Intcode>,
+    boot_output: Option<Intcode>,
+) -> (Receiver<Intcode>, VmThread) {
+    let (tx, output) = channel();
+    if let Some(bo) = boot_output {
+        tx.send(bo).unwrap()
+    }
+    (output, spawn_io(vm, input, tx))
+}
+
+// Same as spawn, but output is written to any destination that can be moved
+// to the thread. The thread stops when the output refuses a value.
+pub fn spawn_io<O: Output + Send + 'static>(
+    vm: Vm,
+    input: Receiver<Intcode>,
+    output: O,
+) -> VmThread {
+    spawn_watched(vm, input, output, |_| ())
+}
+
+// Same as spawn_io, but watch is called with true when the machine starts
+// reading input and with false when it has read a value
+pub(super) fn spawn_watched<O, F>(
+    mut vm: Vm,
+    input: Receiver<Intcode>,
+    mut output: O,
+    mut watch: F,
+) -> VmThread
+where
+    O: Output + Send + 'static,
+    F: FnMut(bool) + Send + 'static,
+{
+    let cancel = Arc::new(AtomicBool::new(false));
+    vm.cancel = Some(cancel.clone());
+    vm.schedule_check();
+    let cancelled = cancel.clone();
+    let handle = thread::spawn(move || {
+        let is_cancelled = || cancelled.load(Ordering::Relaxed);
+        let mut values = iter::from_fn(|| {
+            watch(true);
+            loop {
+                if is_cancelled() {
+                    return None;
+                }
+                match input.recv_timeout(POLL_INTERVAL) {
+                    Ok(val) => {
+                        watch(false);
+                        return Some(val);
+                    }
+                    Err(RecvTimeoutError::Timeout) => (),
+                    Err(RecvTimeoutError::Disconnected) => return None,
+                }
+            }
+        });
+        match vm.run_io(&mut values, &mut output) {
+            Ok(Event::Halted) => Exit::Halted,
+            Ok(Event::Output(_)) => Exit::OutputClosed,
+            Ok(Event::NeedsInput) if is_cancelled() => Exit::Cancelled,
+            Ok(Event::NeedsInput) => Exit::InputClosed,
+            Err(IntcodeError::Cancelled { .. }) => Exit::Cancelled,
+            Err(err) => Exit::Fault(err),
+        }
+    });
+    VmThread { cancel, handle }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num::bigint::BigInt;
+
+    #[test]
+    fn vm_needs_input() {
+        let mut vm = Vm::new(&[3, 0, 4, 0, 99]);
+        assert_eq!(vm.run(), Ok(Event::NeedsInput));
+        assert_eq!(vm.run(), Ok(Event::NeedsInput));
+        vm.push_input(42);
+        assert_eq!(vm.run(), Ok(Event::Output(42)));
+        assert_eq!(vm.run(), Ok(Event::Halted));
+        assert_eq!(vm.run(), Ok(Event::Halted));
+    }
+
+    #[test]
+    fn vm_relative_output() {
+        let program = vec![
+            109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+        ];
+        let mut vm = Vm::new(&program);
+        let mut result = Vec::new();
+        while let Some(val) = vm.output().unwrap() {
+            result.push(val);
+        }
+        assert_eq!(program, result);
+    }
+
+    #[test]
+    fn vm_fork() {
+        let mut vm = Vm::new(&[3, 9, 1, 9, 9, 9, 4, 9, 99, 0]);
+        let mut fork = vm.clone();
+        vm.push_input(3);
+        fork.push_input(20);
+        assert_eq!(fork.output(), Ok(Some(40)));
+        assert_eq!(vm.clone().output(), Ok(Some(6)));
+        assert_eq!(vm.output(), Ok(Some(6)));
+    }
+
+    #[test]
+    fn vm_self_modifying() {
+        // Replaces the already executed Out instruction at address 0 with Halt
+        let mut vm = Vm::new(&[104, 1, 1101, 99, 0, 0, 1105, 1, 0]);
+        assert_eq!(vm.run(), Ok(Event::Output(1)));
+        assert_eq!(vm.run(), Ok(Event::Halted));
+        assert_eq!(vm.ip(), 0);
+    }
+
+    #[test]
+    fn vm_memory_after_halt() {
+        let mut vm = Vm::new(&[1, 0, 0, 0, 99]);
+        vm.poke(1, 4);
+        assert_eq!(vm.run(), Ok(Event::Halted));
+        assert_eq!((vm.peek(0), vm.peek(1), vm.peek(5)), (100, 4, 0));
+    }
+
+    #[test]
+    fn vm_invalid_instruction() {
+        let mut vm = Vm::new(&[1101, 1, 2, 5, 42]);
+        let err = IntcodeError::InvalidInstruction { ip: 4, opcode: 42 };
+        assert_eq!(vm.run(), Err(err));
+    }
+
+    #[test]
+    fn vm_invalid_mode() {
+        let mut vm = Vm::new(&[104, 7, 304, 7, 99]);
+        assert_eq!(vm.run(), Ok(Event::Output(7)));
+        let err = IntcodeError::InvalidMode { ip: 2, opcode: 304 };
+        assert_eq!(vm.run(), Err(err));
+    }
+
+    #[test]
+    fn vm_immediate_store() {
+        let mut vm = Vm::new(&[11101, 1, 2, 3, 99]);
+        let err = vm.run().unwrap_err();
+        assert_eq!(
+            err,
+            IntcodeError::ImmediateStore {
+                ip: 0,
+                opcode: 11101
+            }
+        );
+        assert_eq!((err.ip(), err.opcode()), (0, 11101));
+    }
+
+    #[test]
+    fn spawn_reports_error() {
+        let (_, sink) = channel();
+        let (output, thread) = spawn(Vm::new(&[104, 1, 98]), sink, None);
+        assert_eq!(output.recv(), Ok(1));
+        assert!(output.recv().is_err());
+        let err = IntcodeError::InvalidInstruction { ip: 2, opcode: 98 };
+        assert_eq!(thread.join(), Exit::Fault(err));
+    }
+
+    #[test]
+    fn spawn_cancel() {
+        let (tx, sink) = channel();
+        let (output, thread) = spawn(Vm::new(&[3, 0, 4, 0, 1105, 1, 0]), sink, None);
+        tx.send(7).unwrap();
+        assert_eq!(output.recv(), Ok(7));
+        thread.cancel();
+        assert_eq!(thread.join(), Exit::Cancelled);
+        let (output, thread) = spawn(Vm::new(&[1105, 1, 0]), channel().1, Some(5));
+        assert_eq!(output.recv(), Ok(5));
+        thread.cancel();
+        assert_eq!(thread.join(), Exit::Cancelled);
+        let (_, sink) = channel();
+        let (output, thread) = spawn(Vm::new(&[104, 1, 99]), sink, None);
+        drop(output);
+        assert_eq!(thread.join(), Exit::OutputClosed);
+    }
+
+    #[test]
+    fn vm_words() {
+        // Multiplies the value at address 7 by 4 and outputs it
+        let text = "1002,7,4,7,4,7,99,4611686018427387904";
+        let program = Program::<Intcode>::parse_words(text).unwrap();
+        let mut vm = Vm::from_words(&program);
+        assert_eq!(vm.clone().output(), Ok(Some(0)));
+        vm.check_overflow(true);
+        let err = IntcodeError::Overflow { ip: 0, opcode: 1002 };
+        assert_eq!(vm.output(), Err(err));
+        let program = Program::<i128>::parse_words(text).unwrap();
+        let mut vm = Vm::from_words(&program);
+        vm.check_overflow(true);
+        assert_eq!(vm.output(), Ok(Some(1 << 64)));
+        let program = Program::<BigInt>::parse_words("1002,7,4,7,4,7,99,1e3");
+        assert!(program.is_err());
+        let text = text.replace("4611686018427387904", "1267650600228229401496703205376");
+        let program = Program::<BigInt>::parse_words(&text).unwrap();
+        let out = Vm::from_words(&program).output().unwrap().unwrap();
+        assert_eq!(out.to_string(), "5070602400912917605986812821504");
+    }
+
+    #[test]
+    fn vm_address_overflow() {
+        let mut vm = Vm::from_words(&[109i128, 1 << 64, 99]);
+        let err = IntcodeError::Overflow { ip: 0, opcode: 109 };
+        assert_eq!(vm.run(), Err(err));
+        let mut vm = Vm::new(&[109, Intcode::MAX, 109, 1, 99]);
+        let mut wrapping = vm.clone();
+        assert_eq!(wrapping.run(), Ok(Event::Halted));
+        assert_eq!(wrapping.relative_base(), Intcode::MIN);
+        vm.check_overflow(true);
+        let err = IntcodeError::Overflow { ip: 2, opcode: 109 };
+        assert_eq!(vm.run(), Err(err));
+    }
+
+    #[test]
+    fn vm_budget() {
+        let mut vm = Vm::new(&[1001, 7, 1, 7, 1105, 1, 0, 0]);
+        vm.limit_instructions(Some(5));
+        let budget = Budget::Instructions(5);
+        let err = IntcodeError::BudgetExceeded {
+            ip: 4,
+            opcode: 1105,
+            budget,
+        };
+        assert_eq!(vm.run(), Err(err.clone()));
+        assert_eq!(vm.run(), Err(err));
+        assert_eq!(vm.peek(7), 3);
+        vm.limit_instructions(Some(2));
+        assert!(vm.run().is_err());
+        assert_eq!(vm.peek(7), 4);
+        vm.limit_instructions(None);
+        vm.limit_time(Some(Duration::from_millis(20)));
+        let budget = Budget::Time(Duration::from_millis(20));
+        assert!(
+            matches!(vm.run(), Err(IntcodeError::BudgetExceeded { budget: b, .. }) if b == budget)
+        );
+    }
+}
@@ -0,0 +1,430 @@
+use super::capture::*;
+use super::*;
+
+// Packet sent by the machine at address src. The destination is kept as sent
+// since it may be outside the network.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Packet {
+    pub src: Intcode,
+    pub dst: Intcode,
+    pub x: Intcode,
+    pub y: Intcode,
+}
+
+// What the network does after the NAT has handled a packet or idleness
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Verdict {
+    Continue,
+    Send(Packet),
+    Stop(Intcode),
+}
+
+// Why a network stopped without the NAT stopping it
+#[derive(Debug, PartialEq)]
+pub enum NetworkError {
+    Fault(IntcodeError),
+    // The machine at address src halted or waited for input in the middle of
+    // sending a packet
+    PartialPacket { src: Intcode },
+    // The NAT sent a packet to an address outside the network
+    Undeliverable(Packet),
+    // No machine sent a packet or changed in the round at time, and the NAT
+    // sent nothing to wake them up, so nothing would ever happen again
+    Stalled { time: u64 },
+}
+
+impl fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NetworkError::Fault(err) => write!(f, "{}", err),
+            NetworkError::PartialPacket { src } => {
+                write!(f, "machine {} stopped in the middle of a packet", src)
+            }
+            NetworkError::Undeliverable(packet) => {
+                write!(
+                    f,
+                    "NAT sent a packet to {}, outside the network",
+                    packet.dst
+                )
+            }
+            NetworkError::Stalled { time } => {
+                write!(f, "network stalled at time {}, NAT sent nothing", time)
+            }
+        }
+    }
+}
+
+impl Error for NetworkError {}
+
+impl From<IntcodeError> for NetworkError {
+    fn from(err: IntcodeError) -> NetworkError {
+        NetworkError::Fault(err)
+    }
+}
+
+// Policy for packets sent to addresses outside the network and for when the
+// network is idle
+pub trait Nat {
+    fn receive(&mut self, packet: Packet) -> Verdict;
+    fn idle(&mut self) -> Verdict;
+}
+
+// Machines that send packets of three values (destination, x, y) to each
+// other. The machines are run one at a time, in order of address, until they
+// wait for input, so a run always gives the same result. Time is counted in
+// such rounds.
+pub struct Network {
+    nics: Vec<Vm>,
+    time: u64,
+    capture: Option<SharedCapture>,
+}
+
+impl Network {
+    // Boots one machine per address, each is given its address as first input
+    pub fn new(program: &[Intcode], size: usize) -> Network {
+        let nics = (0..size)
+            .map(|addr| {
+                let mut nic = Vm::new(program);
+                nic.push_input(addr as Intcode);
+                nic
+            })
+            .collect();
+        let network = Network {
+            nics,
+            time: 0,
+            capture: default_capture(),
+        };
+        network.log(Record::Boot { time: 0, size });
+        network
+    }
+
+    fn log(&self, record: Record) {
+        if let Some(capture) = &self.capture {
+            capture.lock().unwrap().push(record);
+        }
+    }
+
+    // Runs every machine until it needs input that hasn't been delivered, it
+    // is then given -1. Returns all packets sent during the round.
+    fn round(&mut self) -> Result<Vec<Packet>, NetworkError> {
+        let mut packets = Vec::new();
+        for (src, nic) in self.nics.iter_mut().enumerate() {
+            let src = src as Intcode;
+            loop {
+                match nic.run()? {
+                    Event::Output(dst) => {
+                        let mut next = || match nic.output()? {
+                            Some(val) => Ok(val),
+                            None => Err(NetworkError::PartialPacket { src }),
+                        };
+                        let (x, y) = (next()?, next()?);
+                        packets.push(Packet { src, dst, x, y });
+                    }
+                    Event::NeedsInput => {
+                        nic.push_input(-1);
+                        break;
+                    }
+                    Event::Halted => break,
+                }
+            }
+        }
+        self.time += 1;
+        Ok(packets)
+    }
+
+    // Returns false if the destination is outside the network
+    fn deliver(&mut self, packet: Packet) -> bool {
+        match self
+            .nics
+            .get_mut(packet.dst as usize)
+            .filter(|_| packet.dst >= 0)
+        {
+            Some(nic) => {
+                nic.push_input(packet.x);
+                nic.push_input(packet.y);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Packets from the NAT must go to a machine in the network
+    fn deliver_nat(&mut self, packet: Packet) -> Result<(), NetworkError> {
+        if self.deliver(packet) {
+            Ok(())
+        } else {
+            Err(NetworkError::Undeliverable(packet))
+        }
+    }
+
+    // Runs the network until the NAT stops it. The network is idle after a
+    // round where every machine waited for input and no packets were
+    // delivered to any of them. Fails with Stalled when a round changes
+    // nothing and the NAT doesn't wake the network up.
+    pub fn run<N: Nat>(&mut self, nat: &mut N) -> Result<Intcode, NetworkError> {
+        // Machines after the last round where no packets were sent, not even by
+        // the NAT
+        let mut quiet: Option<Vec<Vm>> = None;
+        loop {
+            let time = self.time;
+            let mut is_idle = true;
+            let mut verdicts = Vec::new();
+            let packets = self.round()?;
+            let mut is_quiet = packets.is_empty();
+            for packet in packets {
+                self.log(Record::Packet { time, packet });
+                if self.deliver(packet) {
+                    is_idle = false;
+                } else {
+                    verdicts.push(nat.receive(packet));
+                    self.log(Record::Nat {
+                        time,
+                        verdict: *verdicts.last().unwrap(),
+                    });
+                }
+            }
+            if is_idle {
+                verdicts.push(nat.idle());
+                self.log(Record::Idle { time });
+                self.log(Record::Nat {
+                    time,
+                    verdict: *verdicts.last().unwrap(),
+                });
+            }
+            for verdict in verdicts {
+                match verdict {
+                    Verdict::Continue => (),
+                    Verdict::Send(packet) => {
+                        self.deliver_nat(packet)?;
+                        is_quiet = false;
+                    }
+                    Verdict::Stop(result) => return Ok(result),
+                }
+            }
+            let unchanged =
+                |before: &Vec<Vm>| before.iter().zip(&self.nics).all(|(a, b)| a.same_state(b));
+            if is_quiet && quiet.as_ref().is_some_and(unchanged) {
+                return Err(NetworkError::Stalled { time });
+            }
+            quiet = is_quiet.then(|| self.nics.clone());
+        }
+    }
+
+    // Runs the network with the packets and NAT decisions of a log, instead of
+    // the packets actually sent and a NAT. Reports the first packet sent that
+    // differs from the log.
+    pub fn replay(&mut self, records: &[Record]) -> Result<Replay, NetworkError> {
+        let start = self.time;
+        let mut records = records.iter().filter(|r| r.time() >= start).peekable();
+        while records.peek().is_some() {
+            let time = self.time;
+            let mut sent = self.round()?.into_iter();
+            let mut nat_packets = Vec::new();
+            while let Some(record) = records.next_if(|r| r.time() == time) {
+                match *record {
+                    Record::Packet { packet, .. } => {
+                        let actual = sent.next();
+                        if actual != Some(packet) {
+                            return Ok(Replay::Diverged {
+                                time,
+                                expected: Some(packet),
+                                sent: actual,
+                            });
+                        }
+                        self.deliver(packet);
+                    }
+                    Record::Nat {
+                        verdict: Verdict::Send(packet),
+                        ..
+                    } => nat_packets.push(packet),
+                    _ => (),
+                }
+            }
+            if let Some(extra) = sent.next() {
+                return Ok(Replay::Diverged {
+                    time,
+                    expected: None,
+                    sent: Some(extra),
+                });
+            }
+            for packet in nat_packets {
+                self.deliver_nat(packet)?;
+            }
+        }
+        Ok(Replay::Reproduced)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FORWARD: &str = "; Address 0 sends a packet to 1, all forward packets to 255 with y + 1
+                                  In [addr]
+                                  JmpIfTrue [addr], #loop
+                                  Out #1
+                                  Out #10
+                                  Out #20
+                           loop:  In [x]
+                                  Equals [x], #-1, [t]
+                                  JmpIfTrue [t], #loop
+                                  In [y]
+                                  Add [y], #1, [y]
+                                  Out #255
+                                  Out [x]
+                                  Out [y]
+                                  JmpIfTrue #1, #loop
+                           addr:  Data 0
+                           x:     Data 0
+                           y:     Data 0
+                           t:     Data 0";
+
+    // Sends whatever it receives back to address 0 when the network is idle,
+    // until it has received limit packets
+    struct Echo {
+        received: Vec<Packet>,
+        limit: usize,
+    }
+
+    impl Nat for Echo {
+        fn receive(&mut self, packet: Packet) -> Verdict {
+            self.received.push(packet);
+            Verdict::Continue
+        }
+
+        fn idle(&mut self) -> Verdict {
+            match self.received.last() {
+                Some(last) if self.received.len() < self.limit => Verdict::Send(Packet {
+                    src: 255,
+                    dst: 0,
+                    ..*last
+                }),
+                _ => Verdict::Stop(self.received.len() as Intcode),
+            }
+        }
+    }
+
+    #[test]
+    fn net_nat() {
+        let program = assemble(FORWARD).unwrap();
+        let mut nat = Echo {
+            received: Vec::new(),
+            limit: 3,
+        };
+        assert_eq!(Network::new(&program, 2).run(&mut nat), Ok(3));
+        let ys = nat
+            .received
+            .iter()
+            .map(|p| (p.src, p.y))
+            .collect::<Vec<_>>();
+        assert_eq!(ys, vec![(1, 21), (0, 22), (0, 23)]);
+    }
+
+    // Sends every packet it receives to an address that doesn't exist
+    struct Misroute;
+
+    impl Nat for Misroute {
+        fn receive(&mut self, packet: Packet) -> Verdict {
+            Verdict::Send(Packet { dst: 7, ..packet })
+        }
+
+        fn idle(&mut self) -> Verdict {
+            Verdict::Continue
+        }
+    }
+
+    // Never does anything
+    struct Asleep;
+
+    impl Nat for Asleep {
+        fn receive(&mut self, _: Packet) -> Verdict {
+            Verdict::Continue
+        }
+
+        fn idle(&mut self) -> Verdict {
+            Verdict::Continue
+        }
+    }
+
+    const PARTIAL: &str = "; Sends a destination and x, but halts before y
+                                 Out #1
+                                 Out #10
+                                 Halt";
+
+    #[test]
+    fn net_errors() {
+        let program = assemble(FORWARD).unwrap();
+        let err = Network::new(&program, 2).run(&mut Misroute).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "NAT sent a packet to 7, outside the network"
+        );
+        let program = assemble(PARTIAL).unwrap();
+        assert_eq!(
+            Network::new(&program, 2).run(&mut Misroute),
+            Err(NetworkError::PartialPacket { src: 0 })
+        );
+        let program = assemble(FORWARD).unwrap();
+        let err = Network::new(&program, 2).run(&mut Asleep).unwrap_err();
+        assert_eq!(err, NetworkError::Stalled { time: 3 });
+        assert_eq!(
+            err.to_string(),
+            "network stalled at time 3, NAT sent nothing"
+        );
+    }
+
+    #[test]
+    fn net_replay() {
+        let program = assemble(FORWARD).unwrap();
+        let capture = Arc::new(Mutex::new(Vec::new()));
+        let mut network = Network::new(&program, 2);
+        network.capture = Some(capture.clone());
+        let mut nat = Echo {
+            received: Vec::new(),
+            limit: 2,
+        };
+        assert_eq!(network.run(&mut nat), Ok(2));
+        let log = capture.lock().unwrap().clone();
+        let text = log.iter().map(|r| r.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            text,
+            vec![
+                "0 packet 0 1 10 20",
+                "1 packet 1 255 10 21",
+                "1 nat continue",
+                "1 idle",
+                "1 nat send 255 0 10 21",
+                "2 packet 0 255 10 22",
+                "2 nat continue",
+                "2 idle",
+                "2 nat stop 2",
+            ]
+        );
+        let replay = |log: &[Record]| Network::new(&program, 2).replay(log);
+        assert_eq!(replay(&log), Ok(Replay::Reproduced));
+        let mut changed = log.clone();
+        changed[4] = Record::Nat {
+            time: 1,
+            verdict: Verdict::Send(Packet {
+                src: 255,
+                dst: 0,
+                x: 10,
+                y: 30,
+            }),
+        };
+        let packet = |y| Packet {
+            src: 0,
+            dst: 255,
+            x: 10,
+            y,
+        };
+        assert_eq!(
+            replay(&changed),
+            Ok(Replay::Diverged {
+                time: 2,
+                expected: Some(packet(22)),
+                sent: Some(packet(31)),
+            })
+        );
+    }
+}
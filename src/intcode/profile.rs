@@ -0,0 +1,118 @@
+use super::*;
+use std::io::Write;
+
+// Counts executed instructions, both per instruction type and per address
+#[derive(Default)]
+pub struct Profile {
+    total: u64,
+    per_instr: HashMap<Instruction, u64>,
+    per_ip: HashMap<Intcode, (Instruction, u64)>,
+}
+
+impl Observer for Profile {
+    fn instruction(&mut self, ip: Intcode, instr: Instruction, _operands: &[Intcode]) {
+        self.total += 1;
+        *self.per_instr.entry(instr).or_insert(0) += 1;
+        self.per_ip.entry(ip).or_insert((instr, 0)).1 += 1;
+    }
+}
+
+impl Profile {
+    // Summary with the number of executed instructions of each type followed
+    // by the num_hottest most executed addresses
+    pub fn report(&self, num_hottest: usize) -> String {
+        let percent = |count: u64| 100.0 * count as f64 / self.total.max(1) as f64;
+        let mut lines = vec![format!("Instructions executed : {}", self.total)];
+        let mut per_instr = self.per_instr.iter().collect::<Vec<_>>();
+        per_instr.sort_by_key(|&(instr, &count)| (std::cmp::Reverse(count), *instr as Intcode));
+        for (instr, &count) in per_instr {
+            let name = format!("{:?}", instr);
+            lines.push(format!("  {:<12}{:>12} {:>6.2}%", name, count, percent(count)));
+        }
+        lines.push("Hottest instructions".to_string());
+        let mut per_ip = self.per_ip.iter().collect::<Vec<_>>();
+        per_ip.sort_by_key(|&(ip, &(_, count))| (std::cmp::Reverse(count), *ip));
+        for (ip, &(instr, count)) in per_ip.into_iter().take(num_hottest) {
+            let name = format!("{:?}", instr);
+            lines.push(format!(
+                "  {:>6}: {:<12}{:>12} {:>6.2}%",
+                ip,
+                name,
+                count,
+                percent(count)
+            ));
+        }
+        lines.join("\n")
+    }
+}
+
+// Writes every executed instruction, with resolved operands, and every store
+// to memory as text
+pub struct Tracer<W: Write + Send> {
+    out: W,
+}
+
+impl<W: Write + Send> Tracer<W> {
+    pub fn new(out: W) -> Tracer<W> {
+        Tracer { out }
+    }
+}
+
+impl<W: Write + Send> Observer for Tracer<W> {
+    fn instruction(&mut self, ip: Intcode, instr: Instruction, operands: &[Intcode]) {
+        let operands = operands
+            .iter()
+            .map(|op| format!(" {}", op))
+            .collect::<Vec<_>>()
+            .join(",");
+        writeln!(self.out, "{:>6}: {:?}{}", ip, instr, operands).unwrap();
+    }
+
+    fn store(&mut self, addr: Intcode, val: Intcode) {
+        writeln!(self.out, "        [{}] <- {}", addr, val).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COUNTDOWN: [Intcode; 9] = [1001, 8, -1, 8, 1005, 8, 0, 99, 3];
+
+    #[test]
+    fn profile_counts() {
+        let profile = Arc::new(Mutex::new(Profile::default()));
+        let mut vm = Vm::new(&COUNTDOWN);
+        vm.attach(Some(profile.clone()));
+        assert_eq!(vm.run(), Ok(Event::Halted));
+        let profile = profile.lock().unwrap();
+        assert_eq!(profile.total, 7);
+        assert_eq!(
+            profile.report(1),
+            "Instructions executed : 7\n\
+             \x20 Add                    3  42.86%\n\
+             \x20 JmpIfTrue              3  42.86%\n\
+             \x20 Halt                   1  14.29%\n\
+             Hottest instructions\n\
+             \x20      0: Add                    3  42.86%"
+        );
+    }
+
+    #[test]
+    fn trace_operands() {
+        let tracer = Arc::new(Mutex::new(Tracer::new(Vec::new())));
+        let mut vm = Vm::new(&[3, 7, 2, 7, 7, 7, 99, 0]);
+        vm.attach(Some(tracer.clone()));
+        vm.push_input(5);
+        assert_eq!(vm.run(), Ok(Event::Halted));
+        let trace = String::from_utf8(tracer.lock().unwrap().out.clone()).unwrap();
+        assert_eq!(
+            trace,
+            "     0: In 7\n\
+             \x20       [7] <- 5\n\
+             \x20    2: Mul 5, 5, 7\n\
+             \x20       [7] <- 25\n\
+             \x20    6: Halt\n"
+        );
+    }
+}
@@ -0,0 +1,117 @@
+use super::*;
+use std::fs;
+use std::io;
+use std::ops::Deref;
+use std::path::Path;
+
+// Intcode program as read from text, comma separated values that may be
+// surrounded by whitespace and spread over several lines
+#[derive(Clone, Debug, PartialEq)]
+pub struct Program<W = Intcode>(Vec<W>);
+
+// Token that is not a valid value, offset is in bytes from the start of the
+// text. An empty token means a value was expected but none was found.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProgramError {
+    pub offset: usize,
+    pub token: String,
+}
+
+impl fmt::Display for ProgramError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.token.is_empty() {
+            write!(f, "missing value at offset {}", self.offset)
+        } else {
+            write!(
+                f,
+                "invalid value \"{}\" at offset {}",
+                self.token, self.offset
+            )
+        }
+    }
+}
+
+impl Error for ProgramError {}
+
+impl Program {
+    // A single trailing comma is accepted, any other empty value is an error
+    pub fn parse(text: &str) -> Result<Program, ProgramError> {
+        Program::parse_words(text)
+    }
+
+    // Parse errors are reported as invalid data
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Program> {
+        Program::load_words(path)
+    }
+}
+
+impl<W: Word> Program<W> {
+    // Same as parse, but for programs with words of any type
+    pub fn parse_words(text: &str) -> Result<Program<W>, ProgramError> {
+        let tokens = text.split(',').collect::<Vec<_>>();
+        let mut words = Vec::with_capacity(tokens.len());
+        let mut offset = 0;
+        for (idx, token) in tokens.iter().enumerate() {
+            let value = token.trim();
+            let start = offset + token.len() - token.trim_start().len();
+            offset += token.len() + 1;
+            if value.is_empty() && idx > 0 && idx == tokens.len() - 1 {
+                break;
+            }
+            words.push(value.parse::<W>().map_err(|_| ProgramError {
+                offset: start,
+                token: value.to_string(),
+            })?);
+        }
+        Ok(Program(words))
+    }
+
+    pub fn load_words<P: AsRef<Path>>(path: P) -> io::Result<Program<W>> {
+        let text = fs::read_to_string(path)?;
+        Program::parse_words(&text).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+}
+
+impl<W> Deref for Program<W> {
+    type Target = [W];
+
+    fn deref(&self) -> &[W] {
+        &self.0
+    }
+}
+
+impl<W> From<Program<W>> for Vec<W> {
+    fn from(program: Program<W>) -> Vec<W> {
+        program.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn program_whitespace() {
+        let program = Program::parse(" 1, 2,\n-3 ,\r\n\t4,\n").unwrap();
+        assert_eq!(&program[..], &[1, 2, -3, 4]);
+        assert_eq!(Vec::from(program), vec![1, 2, -3, 4]);
+    }
+
+    #[test]
+    fn program_errors() {
+        let err = |offset, token: &str| {
+            Err(ProgramError {
+                offset,
+                token: token.to_string(),
+            })
+        };
+        assert_eq!(Program::parse("1,2,x3,4"), err(4, "x3"));
+        assert_eq!(Program::parse("1,\n  2 3"), err(5, "2 3"));
+        assert_eq!(Program::parse("1,,2"), err(2, ""));
+        assert_eq!(Program::parse("  "), err(2, ""));
+        assert_eq!(
+            Program::parse("99,1.5").unwrap_err().to_string(),
+            "invalid value \"1.5\" at offset 3"
+        );
+    }
+}
@@ -0,0 +1,224 @@
+use super::*;
+
+// Log of a run from which every earlier state of the machine can be restored.
+// Only the values consumed by In instructions are logged, plus a snapshot of
+// the machine every interval instructions. Going back replays the run from the
+// closest snapshot before, feeding it the logged input, so it relies on the
+// program being deterministic and nobody poking memory in between.
+#[derive(Clone)]
+pub struct Recording<W = Intcode> {
+    interval: u64,
+    inputs: Vec<W>,
+    snapshots: Vec<Snapshot<W>>,
+}
+
+// Machine without observer, limits or recording, and the number of logged
+// input values it had consumed
+#[derive(Clone)]
+struct Snapshot<W> {
+    vm: Vm<W>,
+    consumed: usize,
+}
+
+impl<W: Word> Recording<W> {
+    pub fn log_input(&mut self, val: W) {
+        self.inputs.push(val);
+    }
+}
+
+impl<W: Word> Vm<W> {
+    // Starts recording the run from now on, with a snapshot every interval
+    // instructions. Stepping back replays up to that many instructions. None
+    // stops recording.
+    pub fn record(&mut self, interval: Option<u64>) {
+        self.recording = interval.map(|interval| {
+            Box::new(Recording {
+                interval: interval.max(1),
+                inputs: Vec::new(),
+                snapshots: vec![self.snapshot(0)],
+            })
+        });
+    }
+
+    // Number of instructions executed since the machine was created
+    pub fn executed(&self) -> u64 {
+        self.executed
+    }
+
+    fn snapshot(&self, consumed: usize) -> Snapshot<W> {
+        Snapshot {
+            vm: Vm {
+                mem: self.mem.clone(),
+                ip: self.ip,
+                relative_base: self.relative_base,
+                input: VecDeque::new(),
+                observer: None,
+                decoded: self.decoded.clone(),
+                executed: self.executed,
+                next_check: u64::MAX,
+                max_executed: None,
+                deadline: None,
+                cancel: None,
+                checked: self.checked,
+                recording: None,
+                extensions: self.extensions.clone(),
+            },
+            consumed,
+        }
+    }
+
+    // Takes a snapshot before the next instruction is executed if it is time
+    // to
+    pub(super) fn record_step(&mut self) {
+        let consumed = match &self.recording {
+            Some(rec)
+                if self.executed.is_multiple_of(rec.interval)
+                    && rec.snapshots.last().unwrap().vm.executed < self.executed =>
+            {
+                rec.inputs.len()
+            }
+            _ => return,
+        };
+        let snapshot = self.snapshot(consumed);
+        self.recording.as_mut().unwrap().snapshots.push(snapshot);
+    }
+
+    // Whether the instruction at ip stores a value at addr
+    fn stores_to(&mut self, addr: Intcode) -> bool {
+        match self.fetch() {
+            Ok(dec) => (1..=3).any(|offset| {
+                dec.instr.is_store(offset as usize) && self.st_addr(dec, offset).ok() == Some(addr)
+            }),
+            Err(_) => false,
+        }
+    }
+
+    // Runs the recorded run from a snapshot until step instructions have been
+    // executed, calling visit before each instruction. The input queue is the
+    // logged input followed by the input still queued now. None is returned
+    // if the run doesn't get that far.
+    fn replay(
+        &self,
+        snapshot: &Snapshot<W>,
+        step: u64,
+        mut visit: impl FnMut(&mut Vm<W>),
+    ) -> Option<Vm<W>> {
+        let rec = self.recording.as_ref()?;
+        let mut vm = snapshot.vm.clone();
+        vm.input = rec.inputs[snapshot.consumed..]
+            .iter()
+            .chain(&self.input)
+            .cloned()
+            .collect();
+        while vm.executed < step {
+            visit(&mut vm);
+            let executed = vm.executed;
+            // Faults happen again at the same step when running forward
+            vm.step().unwrap_or_default();
+            if vm.executed == executed {
+                return None;
+            }
+        }
+        Some(vm)
+    }
+
+    // Goes back to the state after the first step instructions of the run.
+    // Input consumed since stays queued, so running forward again repeats the
+    // run. Returns false if that part of the run wasn't recorded.
+    pub fn rewind(&mut self, step: u64) -> bool {
+        let rec = match &self.recording {
+            Some(rec) if step <= self.executed => rec,
+            _ => return false,
+        };
+        let snapshot = match rec.snapshots.iter().rev().find(|s| s.vm.executed <= step) {
+            Some(snapshot) => snapshot,
+            None => return false,
+        };
+        let mut vm = match self.replay(snapshot, step, |_| ()) {
+            Some(vm) => vm,
+            None => return false,
+        };
+        let consumed = rec.inputs.len() + self.input.len() - vm.input.len();
+        let mut rec = self.recording.take().unwrap();
+        rec.inputs.truncate(consumed);
+        rec.snapshots.retain(|s| s.vm.executed <= step);
+        vm.recording = Some(rec);
+        vm.observer = self.observer.take();
+        vm.max_executed = self.max_executed;
+        vm.deadline = self.deadline;
+        vm.cancel = self.cancel.take();
+        vm.schedule_check();
+        *self = vm;
+        true
+    }
+
+    // Step and address of the last instruction that stored a value at addr,
+    // the instruction is the next one to execute after rewinding to that step
+    pub fn last_write(&self, addr: Intcode) -> Option<(u64, Intcode)> {
+        let rec = self.recording.as_ref()?;
+        let mut end = self.executed;
+        for snapshot in rec.snapshots.iter().rev() {
+            let mut last = None;
+            self.replay(snapshot, end, |vm| {
+                if vm.stores_to(addr) {
+                    last = Some((vm.executed, vm.ip));
+                }
+            })?;
+            if last.is_some() {
+                return last;
+            }
+            end = snapshot.vm.executed;
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SUM: &str = "; Adds input to a sum until a zero is read, then outputs the sum
+                       loop: In [val]
+                             JmpIfFalse [val], #done
+                             Add [val], [sum], [sum]
+                             JmpIfTrue #1, #loop
+                       done: Out [sum]
+                             Halt
+                       val:  Data 0
+                       sum:  Data 0";
+
+    #[test]
+    fn recording_rewind() {
+        let mut vm = Vm::new(&assemble(SUM).unwrap());
+        vm.record(Some(3));
+        for val in &[5, 7, 9] {
+            vm.push_input(*val);
+        }
+        assert_eq!(vm.run(), Ok(Event::NeedsInput));
+        assert_eq!((vm.executed(), vm.peek(16)), (12, 21));
+        assert!(vm.rewind(6));
+        assert_eq!((vm.ip(), vm.peek(16), vm.pending_input()), (5, 5, 1));
+        assert!(!vm.rewind(7));
+        vm.push_input(0);
+        assert_eq!(vm.output(), Ok(Some(21)));
+        assert!(vm.rewind(0));
+        assert_eq!((vm.peek(15), vm.peek(16), vm.pending_input()), (0, 0, 4));
+        assert_eq!(vm.output(), Ok(Some(21)));
+    }
+
+    #[test]
+    fn recording_last_write() {
+        let mut vm = Vm::new(&assemble(SUM).unwrap());
+        vm.record(Some(4));
+        for val in &[5, 7, 0] {
+            vm.push_input(*val);
+        }
+        assert_eq!(vm.output(), Ok(Some(12)));
+        assert_eq!(vm.last_write(16), Some((6, 5)));
+        assert_eq!(vm.last_write(15), Some((8, 0)));
+        assert_eq!(vm.last_write(99), None);
+        let mut unrecorded = Vm::new(&assemble(SUM).unwrap());
+        assert_eq!(unrecorded.last_write(16), None);
+        assert!(!unrecorded.rewind(0));
+    }
+}
@@ -0,0 +1,152 @@
+use super::*;
+use std::collections::BTreeMap;
+
+const HELP: &str = "\
+!save NAME        take a snapshot of the machine
+!restore NAME     go back to a snapshot
+!snapshots        list the snapshots taken
+!help             show this text
+!quit             leave the session
+Any other line is sent to the program as input";
+
+// Time the program may run after each line of input, stops programs that never
+// wait for input or halt
+const TIME_LIMIT: Duration = Duration::from_secs(10);
+
+// Session with a program that talks ASCII text. Lines are sent to the program
+// as input, except for lines starting with "!" that control the session.
+pub struct Terminal {
+    machine: AsciiMachine,
+    snapshots: BTreeMap<String, AsciiMachine>,
+}
+
+impl Terminal {
+    pub fn new(program: &[Intcode]) -> Terminal {
+        Terminal {
+            machine: AsciiMachine::new(Vm::new(program)),
+            snapshots: BTreeMap::new(),
+        }
+    }
+
+    // Runs the program until it waits for input or halts and returns all it
+    // output, values that aren't ASCII text are shown on lines of their own
+    pub fn resume(&mut self) -> String {
+        self.machine.vm_mut().limit_time(Some(TIME_LIMIT));
+        let mut text = match self.machine.read_all() {
+            Ok(text) => text,
+            Err(err) => return format!("Fault: {}\n", err),
+        };
+        let mut notes = Vec::new();
+        while let Some(val) = self.machine.take_value() {
+            notes.push(format!("Value: {}\n", val));
+        }
+        if self.machine.halted() {
+            notes.push("Halted, type !restore NAME or !quit\n".to_string());
+        }
+        if !notes.is_empty() && !text.is_empty() && !text.ends_with('\n') {
+            text.push('\n');
+        }
+        text + &notes.concat()
+    }
+
+    fn control(&mut self, cmd: &str, name: Option<&str>) -> Result<String, String> {
+        let name = || name.ok_or("Missing snapshot name");
+        Ok(match cmd {
+            "save" => {
+                let name = name()?;
+                self.snapshots
+                    .insert(name.to_string(), self.machine.clone());
+                format!("Saved {}\n", name)
+            }
+            "restore" => {
+                let name = name()?;
+                let snapshot = self
+                    .snapshots
+                    .get(name)
+                    .ok_or(format!("No snapshot named \"{}\"", name))?;
+                self.machine = snapshot.clone();
+                format!("Restored {}\n", name)
+            }
+            "snapshots" => self
+                .snapshots
+                .keys()
+                .map(|name| format!("{}\n", name))
+                .collect(),
+            "help" => format!("{}\n", HELP),
+            _ => return Err(format!("Unknown command \"!{}\", try \"!help\"", cmd)),
+        })
+    }
+
+    // Handles one line typed by the user and returns the text to show, None
+    // is returned when the user wants to quit
+    pub fn command(&mut self, line: &str) -> Option<String> {
+        match line.strip_prefix('!') {
+            Some(control) => {
+                let words = control.split_whitespace().collect::<Vec<_>>();
+                match words.first() {
+                    Some(&"quit") => None,
+                    Some(cmd) => Some(match self.control(cmd, words.get(1).cloned()) {
+                        Ok(text) => text,
+                        Err(err) => format!("{}\n", err),
+                    }),
+                    None => Some(format!("{}\n", HELP)),
+                }
+            }
+            None => {
+                self.machine.send_line(line);
+                Some(self.resume())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOTAL: &str = "; Add up digits typed one per line until the total is 9
+                         loop:  Out #62
+                                In [digit]
+                                In [nl]
+                                Add [digit], #-48, [digit]
+                                Add [total], [digit], [total]
+                                Add [total], #48, [ch]
+                                Out [ch]
+                                Out #10
+                                Equals [total], #9, [done]
+                                JmpIfFalse [done], #loop
+                                Mul [total], #111, [ch]
+                                Out [ch]
+                                Halt
+                         digit: Data 0
+                         nl:    Data 0
+                         total: Data 0
+                         ch:    Data 0
+                         done:  Data 0";
+
+    #[test]
+    fn term_snapshots() {
+        let mut term = Terminal::new(&assemble(TOTAL).unwrap());
+        assert_eq!(term.resume(), ">");
+        assert_eq!(term.command("3"), Some("3\n>".to_string()));
+        assert_eq!(
+            term.command("!save three"),
+            Some("Saved three\n".to_string())
+        );
+        assert_eq!(
+            term.command("6"),
+            Some("9\nValue: 999\nHalted, type !restore NAME or !quit\n".to_string())
+        );
+        assert_eq!(
+            term.command("!restore three"),
+            Some("Restored three\n".to_string())
+        );
+        assert_eq!(term.command("2"), Some("5\n>".to_string()));
+        assert_eq!(term.command("!snapshots"), Some("three\n".to_string()));
+        assert_eq!(
+            term.command("!restore four"),
+            Some("No snapshot named \"four\"\n".to_string())
+        );
+        assert_eq!(term.command("!quit"), None);
+    }
+}
@@ -0,0 +1,116 @@
+use super::Intcode;
+use num::bigint::BigInt;
+use num_traits::{ToPrimitive, Zero};
+use std::fmt::{Debug, Display};
+use std::str::FromStr;
+
+// Type of the values in the memory of a virtual machine. Addresses are always
+// Intcode values, so a word used as an address must fit in an Intcode. The
+// checked operations return None on overflow.
+pub trait Word:
+    Clone + Debug + Display + FromStr + PartialEq + PartialOrd + Send + Sync + 'static
+{
+    fn from_intcode(val: Intcode) -> Self;
+    fn to_intcode(&self) -> Option<Intcode>;
+    fn checked_add(&self, other: &Self) -> Option<Self>;
+    fn checked_mul(&self, other: &Self) -> Option<Self>;
+    fn wrapping_add(&self, other: &Self) -> Self;
+    fn wrapping_mul(&self, other: &Self) -> Self;
+
+    fn is_zero(&self) -> bool {
+        self.to_intcode() == Some(0)
+    }
+
+    // The word as an Intcode, or the Intcode closest to it if it doesn't fit
+    fn saturate(&self) -> Intcode {
+        self.to_intcode().unwrap_or_else(|| {
+            if *self < Self::from_intcode(0) {
+                Intcode::MIN
+            } else {
+                Intcode::MAX
+            }
+        })
+    }
+}
+
+macro_rules! primitive_word {
+    ($type:ty) => {
+        impl Word for $type {
+            fn from_intcode(val: Intcode) -> Self {
+                val as $type
+            }
+
+            fn to_intcode(&self) -> Option<Intcode> {
+                ToPrimitive::to_i64(self)
+            }
+
+            fn checked_add(&self, other: &Self) -> Option<Self> {
+                <$type>::checked_add(*self, *other)
+            }
+
+            fn checked_mul(&self, other: &Self) -> Option<Self> {
+                <$type>::checked_mul(*self, *other)
+            }
+
+            fn wrapping_add(&self, other: &Self) -> Self {
+                <$type>::wrapping_add(*self, *other)
+            }
+
+            fn wrapping_mul(&self, other: &Self) -> Self {
+                <$type>::wrapping_mul(*self, *other)
+            }
+        }
+    };
+}
+
+primitive_word!(i64);
+primitive_word!(i128);
+
+// Arbitrary precision words never overflow
+impl Word for BigInt {
+    fn from_intcode(val: Intcode) -> Self {
+        BigInt::from(val)
+    }
+
+    fn to_intcode(&self) -> Option<Intcode> {
+        ToPrimitive::to_i64(self)
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        Some(self + other)
+    }
+
+    fn checked_mul(&self, other: &Self) -> Option<Self> {
+        Some(self * other)
+    }
+
+    fn wrapping_add(&self, other: &Self) -> Self {
+        self + other
+    }
+
+    fn wrapping_mul(&self, other: &Self) -> Self {
+        self * other
+    }
+
+    fn is_zero(&self) -> bool {
+        Zero::is_zero(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn word_overflow() {
+        let big = Intcode::MAX;
+        assert_eq!(Word::checked_add(&big, &1), None);
+        assert_eq!(Word::wrapping_add(&big, &1), Intcode::MIN);
+        let wide = Word::checked_mul(&(big as i128), &4).unwrap();
+        assert_eq!((wide.to_intcode(), wide.saturate()), (None, Intcode::MAX));
+        let huge = Word::checked_mul(&BigInt::from(big), &BigInt::from(-big)).unwrap();
+        assert_eq!(huge.to_string(), "-85070591730234615847396907784232501249");
+        assert_eq!(huge.saturate(), Intcode::MIN);
+        assert!(Word::is_zero(&BigInt::from_intcode(0)));
+    }
+}
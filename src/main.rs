@@ -3,6 +3,7 @@ use std::fs::File;
 use std::io::{BufRead, BufReader};
 
 mod intcode;
+mod tools;
 mod vec2d;
 
 mod day1;
@@ -76,7 +77,9 @@ fn solution_get(day: Day, input: &mut dyn BufRead) -> Box<dyn Solution> {
 fn main() {
     let args: Vec<String> = env::args().collect();
     if args.len() < 2 {
-        println!("Usage: {} DAY INPUT_FILE", args[0])
+        tools::usage(&args[0])
+    } else if args[1].parse::<Day>().is_err() {
+        tools::run(&args[0], &args[1], &args[2..])
     } else {
         let day = args[1].parse::<Day>().unwrap();
         let f = if args.len() == 2 || args[2] == "-v" {
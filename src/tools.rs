@@ -0,0 +1,381 @@
+use super::intcode::*;
+use super::{solution_get, Day, Solution};
+use num::bigint::BigInt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+fn program_get(path: &str) -> Program {
+    Program::load(path).unwrap_or_else(|err| panic!("{}: {}", path, err))
+}
+
+fn disasm(path: &str) {
+    for line in disassemble(&program_get(path)) {
+        println!("{}", line);
+    }
+}
+
+// Prints the control-flow graph of the program in the DOT language, or a
+// summary of what the analysis found
+fn cfg(path: &str, dot: bool) {
+    let analysis = analyze(&program_get(path));
+    if dot {
+        print!("{}", analysis.dot());
+    } else {
+        print!("{}", analysis.summary());
+    }
+}
+
+fn decompile_file(path: &str) {
+    print!("{}", decompile(&program_get(path)));
+}
+
+// Runs random programs on the machine and on a reference interpreter, the
+// seed is taken from the clock unless given
+fn fuzz_programs(rounds: Option<&String>, seed: Option<&String>) {
+    let rounds = rounds.map_or(10_000, |r| r.parse().expect("Invalid number of rounds"));
+    let seed = seed.map_or_else(
+        || {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_nanos() as u64
+        },
+        |s| s.parse().expect("Invalid seed"),
+    );
+    match fuzz(rounds, seed) {
+        Some(divergence) => print!("Seed {}, shrunk to\n{}", seed, divergence),
+        None => println!("Seed {}, {} programs ran the same", seed, rounds),
+    }
+}
+
+fn asm(path: &str) {
+    let source = fs::read_to_string(path).expect("Failed to open assembly file");
+    match assemble(&source) {
+        Ok(program) => println!(
+            "{}",
+            program
+                .iter()
+                .map(|ic| ic.to_string())
+                .collect::<Vec<_>>()
+                .join(",")
+        ),
+        Err(err) => println!("{}: {}", path, err),
+    }
+}
+
+fn debug(path: &str) {
+    let mut dbg = Debugger::new(&program_get(path));
+    let stdin = io::stdin();
+    print!("> ");
+    io::stdout().flush().unwrap();
+    for line in stdin.lock().lines() {
+        match dbg.command(&line.unwrap()) {
+            Some(resp) if resp.is_empty() => (),
+            Some(resp) => println!("{}", resp),
+            None => break,
+        }
+        print!("> ");
+        io::stdout().flush().unwrap();
+    }
+}
+
+// Connects a program that talks ASCII text to the terminal. Commands from the
+// transcript are replayed before reading from stdin, commands typed are
+// appended to the history file, which can be replayed as a transcript later.
+fn play(path: &str, history: Option<&String>, transcript: Option<&String>) {
+    let mut term = Terminal::new(&program_get(path));
+    let mut history = history.map(|file| {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(file)
+            .expect("Failed to open history file")
+    });
+    let transcript = transcript
+        .map(|file| fs::read_to_string(file).expect("Failed to open transcript file"))
+        .unwrap_or_default();
+    print!("{}", term.resume());
+    io::stdout().flush().unwrap();
+    let stdin = io::stdin();
+    let replayed = transcript.lines().map(|line| (true, line.to_string()));
+    let typed = stdin.lock().lines().map(|line| (false, line.unwrap()));
+    for (is_replayed, line) in replayed.chain(typed) {
+        if is_replayed {
+            println!("{}", line);
+        }
+        match term.command(&line) {
+            Some(text) => print!("{}", text),
+            None => break,
+        }
+        io::stdout().flush().unwrap();
+        if let (false, Some(file)) = (is_replayed, &mut history) {
+            writeln!(file, "{}", line).expect("Failed to write history file");
+        }
+    }
+}
+
+fn solution_load(day: &str, path: Option<&String>) -> Box<dyn Solution> {
+    let day = day.parse::<Day>().expect("Invalid day specified");
+    let default_path = format!("src/day{}/input.txt", day);
+    let f = File::open(path.unwrap_or(&default_path)).expect("Failed to open input file");
+    solution_get(day, &mut BufReader::new(f))
+}
+
+// Runs both parts of a day with a profiler attached to all Intcode machines
+fn profile(day: &str, path: Option<&String>) {
+    let solution = solution_load(day, path);
+    let parts: [(&str, &dyn Fn() -> String); 2] = [
+        ("Part 1", &|| solution.part1()),
+        ("Part 2", &|| solution.part2()),
+    ];
+    for (name, part) in parts.iter() {
+        let profile = Arc::new(Mutex::new(Profile::default()));
+        observe_all(Some(profile.clone()));
+        println!("🕯️  {} : {}", name, part());
+        observe_all(None);
+        println!("{}", profile.lock().unwrap().report(10));
+    }
+}
+
+// Solves one part of a day and prints the traffic of the Intcode network it
+// ran, one record per line. The answer goes to stderr so that stdout can be
+// saved as a capture file.
+fn capture(day: &str, part: &str, path: Option<&String>) {
+    let solution = solution_load(day, path);
+    let log = Arc::new(Mutex::new(Vec::new()));
+    capture_all(Some(log.clone()));
+    let answer = match part {
+        "1" => solution.part1(),
+        "2" => solution.part2(),
+        _ => panic!("Invalid part specified"),
+    };
+    capture_all(None);
+    eprintln!("🕯️  Part {} : {}", part, answer);
+    for record in log.lock().unwrap().iter() {
+        println!("{}", record);
+    }
+}
+
+// Runs a network of the program, feeding it the packets and NAT decisions of
+// a capture file, and reports whether the machines sent the same packets
+fn replay(path: &str, capture: &str) {
+    let text = fs::read_to_string(capture).expect("Failed to open capture file");
+    let records = text
+        .lines()
+        .enumerate()
+        .map(|(idx, line)| {
+            Record::parse(line).unwrap_or_else(|| panic!("{}:{}: invalid record", capture, idx + 1))
+        })
+        .collect::<Vec<_>>();
+    let size = match records.first() {
+        Some(Record::Boot { size, .. }) => *size,
+        _ => panic!("{}: capture doesn't start with a boot record", capture),
+    };
+    match Network::new(&program_get(path), size).replay(&records) {
+        Ok(Replay::Reproduced) => println!("Reproduced {} records", records.len()),
+        Ok(Replay::Diverged {
+            time,
+            expected,
+            sent,
+        }) => {
+            let show = |packet: Option<Packet>| match packet {
+                Some(packet) => packet.to_string(),
+                None => "nothing".to_string(),
+            };
+            println!(
+                "Diverged at time {}, expected {} but sent {}",
+                time,
+                show(expected),
+                show(sent)
+            );
+        }
+        Err(err) => println!("Failed: {}", err),
+    }
+}
+
+// Runs a program with the given input and prints every executed instruction
+fn trace(path: &str, input: &[String]) {
+    let mut vm = Vm::new(&program_get(path));
+    vm.attach(Some(Arc::new(Mutex::new(Tracer::new(io::stdout())))));
+    let mut input = input
+        .iter()
+        .map(|val| val.parse::<Intcode>().expect("Invalid input value"));
+    match vm.run_io(&mut input, &mut |val| println!("Output: {}", val)) {
+        Ok(Event::NeedsInput) => println!("Waiting for input"),
+        Ok(_) => println!("Halted"),
+        Err(err) => println!("Fault: {}", err),
+    }
+}
+
+// Runs a program with words of type W, printing every value output
+fn exec_words<W: Word>(path: &str, input: &[String], flags: &[String]) {
+    let program = Program::<W>::load_words(path).unwrap_or_else(|err| panic!("{}: {}", path, err));
+    let mut vm = Vm::from_words(&program);
+    for flag in flags {
+        match flag.as_str() {
+            "--checked" => vm.check_overflow(true),
+            "--extra" => vm.extra_instructions(),
+            _ => panic!("Unknown option {}", flag),
+        }
+    }
+    let mut input = input
+        .iter()
+        .map(|val| val.parse::<W>().ok().expect("Invalid input value"));
+    match vm.run_io(&mut input, &mut |val| println!("Output: {}", val)) {
+        Ok(Event::NeedsInput) => println!("Waiting for input"),
+        Ok(_) => println!("Halted"),
+        Err(err) => println!("Fault: {}", err),
+    }
+}
+
+// Options come before the program file
+fn exec(words: &str, args: &[String]) {
+    let num_flags = args.iter().take_while(|arg| arg.starts_with("--")).count();
+    let (flags, args) = args.split_at(num_flags);
+    let (path, input) = args.split_first().expect("Missing program file");
+    match words {
+        "i64" => exec_words::<Intcode>(path, input, flags),
+        "i128" => exec_words::<i128>(path, input, flags),
+        "big" => exec_words::<BigInt>(path, input, flags),
+        _ => panic!("Invalid word type, expected i64, i128 or big"),
+    }
+}
+
+// Rounds run by bench, the fastest one counts
+const BENCH_ROUNDS: usize = 5;
+
+// Runs each of fs once per round, taking turns so that they are measured under
+// the same conditions. Returns the fastest time of each in microseconds.
+fn fastest(fs: &mut [&mut dyn FnMut()]) -> Vec<u128> {
+    let mut best = vec![u128::MAX; fs.len()];
+    for _ in 0..BENCH_ROUNDS {
+        for (f, best) in fs.iter_mut().zip(best.iter_mut()) {
+            let start = Instant::now();
+            f();
+            *best = (*best).min(start.elapsed().as_micros());
+        }
+    }
+    best
+}
+
+// Compares the Intcode machine with the reference interpreter on the BOOST
+// program of day 9, which only computes, then how long it takes to solve days
+// with the decoded instruction cache of the machines switched off and on
+fn bench(days: &[String]) {
+    let program = program_get("src/day9/input.txt");
+    let boost = |cache: bool| {
+        use_decode_cache(cache);
+        let mut vm = Vm::new(&program);
+        vm.push_input(2);
+        vm.output().unwrap();
+        vm.executed()
+    };
+    println!(
+        "Day 9 part 2, {} instructions, fastest of {} rounds",
+        boost(true),
+        BENCH_ROUNDS
+    );
+    let times = fastest(&mut [
+        &mut || {
+            run_reference(&program, &[2]).unwrap();
+        },
+        &mut || {
+            boost(false);
+        },
+        &mut || {
+            boost(true);
+        },
+    ]);
+    let names = [
+        "Reference interpreter",
+        "Vm, decode cache off",
+        "Vm, decode cache on",
+    ];
+    for (name, time) in names.iter().zip(&times) {
+        println!(
+            "{:<22}: {:>8} µs ({:.2}x)",
+            name,
+            time,
+            times[0] as f64 / *time as f64
+        );
+    }
+    let heavy_days = ["19", "21", "23", "25"]
+        .iter()
+        .map(|d| d.to_string())
+        .collect::<Vec<_>>();
+    for day in if days.is_empty() { &heavy_days } else { days } {
+        let solution = solution_load(day, None);
+        let solve = |cache: bool| {
+            use_decode_cache(cache);
+            solution.part1();
+            solution.part2();
+        };
+        let times = fastest(&mut [&mut || solve(false), &mut || solve(true)]);
+        println!(
+            "Day {:>2} : {:>8} µs cache off {:>8} µs cache on ({:.2}x)",
+            day,
+            times[0],
+            times[1],
+            times[0] as f64 / times[1] as f64
+        );
+    }
+    use_decode_cache(true);
+}
+
+pub fn usage(prog: &str) {
+    println!("Usage: {} DAY INPUT_FILE", prog);
+    println!("       {} asm SOURCE_FILE", prog);
+    println!("       {} bench [DAY...]", prog);
+    println!("       {} capture DAY PART [INPUT_FILE]", prog);
+    println!("       {} cfg INTCODE_FILE [--dot]", prog);
+    println!("       {} debug INTCODE_FILE", prog);
+    println!("       {} decompile INTCODE_FILE", prog);
+    println!("       {} disasm INTCODE_FILE", prog);
+    println!(
+        "       {} exec i64|i128|big [--checked] [--extra] INTCODE_FILE [INPUT...]",
+        prog
+    );
+    println!("       {} fuzz [ROUNDS] [SEED]", prog);
+    println!(
+        "       {} play INTCODE_FILE [--history FILE] [--replay FILE]",
+        prog
+    );
+    println!("       {} profile DAY [INPUT_FILE]", prog);
+    println!("       {} replay INTCODE_FILE CAPTURE_FILE", prog);
+    println!("       {} trace INTCODE_FILE [INPUT...]", prog);
+}
+
+// Value following the option name in the arguments
+fn option<'a>(args: &'a [String], name: &str) -> Option<&'a String> {
+    args.iter()
+        .position(|arg| arg == name)
+        .and_then(|idx| args.get(idx + 1))
+}
+
+// Runs one of the Intcode tools, args are the arguments following the name of
+// the tool
+pub fn run(prog: &str, tool: &str, args: &[String]) {
+    match (tool, args) {
+        ("asm", [path]) => asm(path),
+        ("bench", days) => bench(days),
+        ("capture", [day, part]) => capture(day, part, None),
+        ("capture", [day, part, path]) => capture(day, part, Some(path)),
+        ("cfg", [path]) => cfg(path, false),
+        ("cfg", [path, flag]) if flag == "--dot" => cfg(path, true),
+        ("debug", [path]) => debug(path),
+        ("decompile", [path]) => decompile_file(path),
+        ("disasm", [path]) => disasm(path),
+        ("exec", [words, args @ ..]) if !args.is_empty() => exec(words, args),
+        ("fuzz", args) if args.len() <= 2 => fuzz_programs(args.first(), args.get(1)),
+        ("play", [path, opts @ ..]) => {
+            play(path, option(opts, "--history"), option(opts, "--replay"))
+        }
+        ("profile", [day]) => profile(day, None),
+        ("profile", [day, path]) => profile(day, Some(path)),
+        ("replay", [path, capture]) => replay(path, capture),
+        ("trace", [path, input @ ..]) => trace(path, input),
+        _ => usage(prog),
+    }
+}